@@ -0,0 +1,169 @@
+use std::fs;
+use std::hint::black_box;
+use std::path::Path;
+
+use criterion::{Criterion, criterion_group, criterion_main};
+
+use deadlock_dumper::analysis::{self, ScanOptions};
+
+/// The size of the code section of each synthetic module. The real `client.dll` is a lot bigger,
+/// but this is enough for the scan time to be dominated by matching.
+const CODE_SIZE: usize = 0x800000;
+
+/// Where the snippets are placed, close to the end of the code so every pattern has to go through
+/// almost all of it, while leaving room for their RIP-relative targets.
+const SNIPPETS_OFFSET: usize = CODE_SIZE - 0x1000;
+
+/// Encodings of a few of the registered patterns, with every RIP-relative operand set to 0x100.
+fn snippets(module_name: &str) -> &'static [&'static [u8]] {
+    match module_name {
+        "client.dll" => &[
+            // mov [rip + 0x100], rsi; test rsi, rsi; followed by the entry indexing
+            &[
+                0x48, 0x89, 0x35, 0x00, 0x01, 0x00, 0x00, 0x48, 0x85, 0xF6, 0x81, 0xE2, 0xFF, 0x01,
+                0x00, 0x00, 0x48, 0x6B, 0xC2, 0x78,
+            ],
+            // mov [rip + 0x100], rdx; mov [rdx + ...], rax
+            &[0x48, 0x89, 0x15, 0x00, 0x01, 0x00, 0x00, 0x48, 0x89, 0x42],
+            // lea rax, [r15 + 0x1000]; imul r14, r12, ...
+            &[0x49, 0x8D, 0x87, 0x00, 0x10, 0x00, 0x00, 0x4D, 0x69, 0xF4],
+        ],
+        "engine2.dll" => &[
+            // mov eax, [rip + 0x100]; mov [rdi], eax
+            &[0x8B, 0x05, 0x00, 0x01, 0x00, 0x00, 0x89, 0x07],
+            // mov eax, [rip + 0x100]; mov [rbx], eax
+            &[0x8B, 0x05, 0x00, 0x01, 0x00, 0x00, 0x89, 0x03],
+        ],
+        _ => &[],
+    }
+}
+
+/// Builds a 64-bit image with a single executable section filled with `int3`, followed by the
+/// snippets of `module_name`.
+fn synthetic_module(module_name: &str) -> Vec<u8> {
+    const HEADER_SIZE: usize = 0x1000;
+
+    let mut image = vec![0xCC; HEADER_SIZE + CODE_SIZE];
+
+    image[..HEADER_SIZE].fill(0);
+
+    let mut put = |offset: usize, bytes: &[u8]| {
+        image[offset..offset + bytes.len()].copy_from_slice(bytes);
+    };
+
+    // IMAGE_DOS_HEADER
+    put(0x00, b"MZ");
+    put(0x3C, &0x40u32.to_le_bytes());
+
+    // IMAGE_NT_HEADERS64
+    put(0x40, b"PE\0\0");
+    put(0x44, &0x8664u16.to_le_bytes()); // Machine
+    put(0x46, &1u16.to_le_bytes()); // NumberOfSections
+    put(0x54, &0xF0u16.to_le_bytes()); // SizeOfOptionalHeader
+    put(0x56, &0x2022u16.to_le_bytes()); // Characteristics
+
+    // IMAGE_OPTIONAL_HEADER64
+    put(0x58, &0x20Bu16.to_le_bytes()); // Magic
+    put(0x70, &0x180000000u64.to_le_bytes()); // ImageBase
+    put(0x78, &(HEADER_SIZE as u32).to_le_bytes()); // SectionAlignment
+    put(0x7C, &(HEADER_SIZE as u32).to_le_bytes()); // FileAlignment
+    put(0x90, &((HEADER_SIZE + CODE_SIZE) as u32).to_le_bytes()); // SizeOfImage
+    put(0x94, &(HEADER_SIZE as u32).to_le_bytes()); // SizeOfHeaders
+    put(0xC4, &16u32.to_le_bytes()); // NumberOfRvaAndSizes
+
+    // IMAGE_SECTION_HEADER
+    put(0x148, b".text\0\0\0");
+    put(0x150, &(CODE_SIZE as u32).to_le_bytes()); // VirtualSize
+    put(0x154, &(HEADER_SIZE as u32).to_le_bytes()); // VirtualAddress
+    put(0x158, &(CODE_SIZE as u32).to_le_bytes()); // SizeOfRawData
+    put(0x15C, &(HEADER_SIZE as u32).to_le_bytes()); // PointerToRawData
+    put(0x16C, &0x60000020u32.to_le_bytes()); // Characteristics
+
+    let mut offset = HEADER_SIZE + SNIPPETS_OFFSET;
+
+    for snippet in snippets(module_name) {
+        put(offset, snippet);
+
+        offset += snippet.len() + 0x10;
+    }
+
+    image
+}
+
+/// The names of every module patterns are registered for.
+fn module_names() -> Vec<&'static str> {
+    let mut names: Vec<_> = analysis::pattern_sources()
+        .into_values()
+        .map(|(module_name, _)| module_name)
+        .collect();
+
+    names.sort();
+    names.dedup();
+
+    names
+}
+
+/// Writes the synthetic modules in the format read by [`analysis::offsets_from_cache`].
+fn write_cache(dir: &Path) {
+    fs::create_dir_all(dir).unwrap();
+
+    for module_name in module_names() {
+        let image = synthetic_module(module_name);
+
+        fs::write(
+            dir.join(format!("{}.json", module_name)),
+            serde_json::json!({ "base": 0x180000000u64, "size": image.len() }).to_string(),
+        )
+        .unwrap();
+
+        fs::write(dir.join(format!("{}.bin", module_name)), image).unwrap();
+    }
+}
+
+/// Each module on its own, scanning one pattern after another.
+fn per_module(c: &mut Criterion) {
+    let mut group = c.benchmark_group("scan_image");
+
+    group.sample_size(10);
+
+    for module_name in module_names() {
+        let image = synthetic_module(module_name);
+
+        group.bench_function(module_name, |b| {
+            b.iter(|| analysis::scan_image(black_box(&image), module_name).unwrap())
+        });
+    }
+
+    group.finish();
+}
+
+/// Every module at once, through the parallel scan, both on a single thread and on every core.
+fn total(c: &mut Criterion) {
+    let dir = std::env::temp_dir().join("deadlock-dumper-bench");
+
+    write_cache(&dir);
+
+    let options = ScanOptions::default();
+
+    let mut group = c.benchmark_group("offsets_from_cache");
+
+    group.sample_size(10);
+
+    let single = rayon::ThreadPoolBuilder::new()
+        .num_threads(1)
+        .build()
+        .unwrap();
+
+    group.bench_function("sequential", |b| {
+        b.iter(|| single.install(|| analysis::offsets_from_cache(&dir, &options, &()).unwrap()))
+    });
+
+    group.bench_function("parallel", |b| {
+        b.iter(|| analysis::offsets_from_cache(&dir, &options, &()).unwrap())
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, per_module, total);
+criterion_main!(benches);
@@ -1,18 +1,28 @@
 use std::collections::BTreeMap;
 
+#[cfg(feature = "scan")]
 use anyhow::{Result, bail};
 
+#[cfg(feature = "scan")]
 use log::debug;
 
+#[cfg(feature = "scan")]
 use memflow::prelude::v1::*;
 
+#[cfg(not(feature = "scan"))]
+use super::umem;
+
+#[cfg(feature = "scan")]
 use pelite::pattern;
+#[cfg(feature = "scan")]
 use pelite::pe64::{Pe, PeView};
 
+#[cfg(feature = "scan")]
 use crate::source2::KeyButton;
 
 pub type ButtonMap = BTreeMap<String, umem>;
 
+#[cfg(feature = "scan")]
 pub fn buttons<P: Process + MemoryView>(process: &mut P) -> Result<ButtonMap> {
     let module = process.module_by_name("client.dll")?;
 
@@ -36,6 +46,7 @@ pub fn buttons<P: Process + MemoryView>(process: &mut P) -> Result<ButtonMap> {
     read_buttons(process, &module, list_head)
 }
 
+#[cfg(feature = "scan")]
 fn read_buttons(
     mem: &mut impl MemoryView,
     module: &ModuleInfo,
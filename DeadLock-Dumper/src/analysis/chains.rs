@@ -0,0 +1,169 @@
+use std::collections::BTreeMap;
+
+use log::warn;
+
+use serde::Serialize;
+
+use super::{OffsetMap, SchemaMap};
+
+pub type ChainMap = BTreeMap<&'static str, Vec<ResolvedHop>>;
+
+/// One step of a pointer chain, naming the value that's added to the address.
+#[derive(Clone, Copy, Debug)]
+pub enum Hop {
+    /// An offset of a module (`module`, `name`), which starts from the module's base.
+    Offset(&'static str, &'static str),
+    /// A field declared by a schema class (`class`, `name`), which starts from the pointer read by
+    /// the previous hop.
+    Field(&'static str, &'static str),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ResolvedHop {
+    Offset {
+        module: String,
+        name: String,
+        value: u64,
+    },
+    Field {
+        class: String,
+        name: String,
+        value: u64,
+    },
+}
+
+/// The pointer chains written to `chains.json`. Every hop but the last dereferences the pointer
+/// at the address it leads to, so the last one is the address of the value itself.
+#[rustfmt::skip]
+pub static CHAINS: &[(&str, &[Hop])] = &[
+    ("local_controller", &[
+        Hop::Offset("client.dll", "dwLocalPlayerController"),
+    ]),
+    ("local_pawn", &[
+        Hop::Offset("client.dll", "dwLocalPlayerPawn"),
+    ]),
+    ("local_player_name", &[
+        Hop::Offset("client.dll", "dwLocalPlayerController"),
+        Hop::Field("CBasePlayerController", "m_iszPlayerName"),
+    ]),
+    ("local_origin", &[
+        Hop::Offset("client.dll", "dwLocalPlayerPawn"),
+        Hop::Field("C_BaseEntity", "m_pGameSceneNode"),
+        Hop::Field("CGameSceneNode", "m_vecAbsOrigin"),
+    ]),
+    ("local_health", &[
+        Hop::Offset("client.dll", "dwLocalPlayerPawn"),
+        Hop::Field("C_BaseEntity", "m_iHealth"),
+    ]),
+    ("game_paused", &[
+        Hop::Offset("client.dll", "dwGameRules"),
+        Hop::Field("C_GameRules", "m_bGamePaused"),
+    ]),
+];
+
+/// Resolves the hops of every chain in [`CHAINS`] to the dumped offsets and schema fields. Chains
+/// with a hop that wasn't found are skipped.
+pub fn resolve_chains(offsets: &OffsetMap, schemas: &SchemaMap) -> ChainMap {
+    CHAINS
+        .iter()
+        .filter_map(|(chain_name, hops)| {
+            let resolved = hops
+                .iter()
+                .map(|hop| {
+                    let resolved = resolve_hop(*hop, offsets, schemas);
+
+                    if resolved.is_none() {
+                        warn!(
+                            "failed to resolve {:?} of the \"{}\" chain",
+                            hop, chain_name
+                        );
+                    }
+
+                    resolved
+                })
+                .collect::<Option<_>>()?;
+
+            Some((*chain_name, resolved))
+        })
+        .collect()
+}
+
+fn resolve_hop(hop: Hop, offsets: &OffsetMap, schemas: &SchemaMap) -> Option<ResolvedHop> {
+    match hop {
+        Hop::Offset(module_name, name) => Some(ResolvedHop::Offset {
+            module: module_name.to_string(),
+            name: name.to_string(),
+            value: *offsets.get(module_name)?.get(name)? as u64,
+        }),
+        Hop::Field(class_name, name) => {
+            let field = schemas
+                .values()
+                .flat_map(|(classes, _)| classes)
+                .filter(|class| class.name == class_name)
+                .flat_map(|class| &class.fields)
+                .find(|field| field.name == name)?;
+
+            Some(ResolvedHop::Field {
+                class: class_name.to_string(),
+                name: name.to_string(),
+                value: u64::try_from(field.offset).ok()?,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::analysis::fixtures::class;
+
+    #[test]
+    fn local_origin() {
+        let offsets = OffsetMap::from([(
+            "client.dll".to_string(),
+            BTreeMap::from([("dwLocalPlayerPawn".to_string(), 0x1B80)]),
+        )]);
+
+        let schemas = SchemaMap::from([(
+            "client.dll".to_string(),
+            (
+                vec![
+                    class("C_BaseEntity", None, &[("m_pGameSceneNode", 0x330)]),
+                    class("CGameSceneNode", None, &[("m_vecAbsOrigin", 0xD0)]),
+                ],
+                Vec::new(),
+            ),
+        )]);
+
+        let chains = resolve_chains(&offsets, &schemas);
+
+        assert_eq!(
+            chains["local_origin"],
+            [
+                ResolvedHop::Offset {
+                    module: "client.dll".to_string(),
+                    name: "dwLocalPlayerPawn".to_string(),
+                    value: 0x1B80,
+                },
+                ResolvedHop::Field {
+                    class: "C_BaseEntity".to_string(),
+                    name: "m_pGameSceneNode".to_string(),
+                    value: 0x330,
+                },
+                ResolvedHop::Field {
+                    class: "CGameSceneNode".to_string(),
+                    name: "m_vecAbsOrigin".to_string(),
+                    value: 0xD0,
+                },
+            ]
+        );
+
+        assert!(chains.contains_key("local_pawn"));
+
+        // Neither the local controller nor the health field were found.
+        assert!(!chains.contains_key("local_player_name"));
+        assert!(!chains.contains_key("local_health"));
+    }
+}
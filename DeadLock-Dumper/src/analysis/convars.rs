@@ -0,0 +1,195 @@
+use std::collections::BTreeMap;
+
+#[cfg(feature = "scan")]
+use anyhow::{Result, anyhow};
+
+#[cfg(feature = "scan")]
+use log::debug;
+
+#[cfg(feature = "scan")]
+use memflow::prelude::v1::*;
+
+use serde::Serialize;
+
+#[cfg(feature = "scan")]
+use super::InterfaceMap;
+
+#[cfg(feature = "scan")]
+use crate::source2::Cvar;
+
+pub type ConVarMap = BTreeMap<String, ConVar>;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ConVar {
+    pub flags: u64,
+    /// The names of the known flags that are set, e.g. `FCVAR_CHEAT`.
+    pub flag_names: Vec<&'static str>,
+    /// `None` for types that can't be represented, or if the value couldn't be read.
+    pub default: Option<ConVarValue>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum ConVarValue {
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    String(String),
+    /// Colors, vectors and angles.
+    Components(Vec<f64>),
+}
+
+#[cfg(feature = "scan")]
+#[rustfmt::skip]
+const FLAGS: &[(u64, &str)] = &[
+    (1 << 1,  "FCVAR_DEVELOPMENTONLY"),
+    (1 << 2,  "FCVAR_GAMEDLL"),
+    (1 << 3,  "FCVAR_CLIENTDLL"),
+    (1 << 4,  "FCVAR_HIDDEN"),
+    (1 << 5,  "FCVAR_PROTECTED"),
+    (1 << 6,  "FCVAR_SPONLY"),
+    (1 << 7,  "FCVAR_ARCHIVE"),
+    (1 << 8,  "FCVAR_NOTIFY"),
+    (1 << 9,  "FCVAR_USERINFO"),
+    (1 << 11, "FCVAR_UNLOGGED"),
+    (1 << 13, "FCVAR_REPLICATED"),
+    (1 << 14, "FCVAR_CHEAT"),
+    (1 << 16, "FCVAR_DEMO"),
+    (1 << 17, "FCVAR_DONTRECORD"),
+    (1 << 19, "FCVAR_RELEASE"),
+    (1 << 22, "FCVAR_NOT_CONNECTED"),
+    (1 << 28, "FCVAR_SERVER_CAN_EXECUTE"),
+    (1 << 29, "FCVAR_SERVER_CANNOT_QUERY"),
+    (1 << 30, "FCVAR_CLIENTCMD_CAN_EXECUTE"),
+];
+
+/// Walks the convars registered with the `VEngineCvar007` interface of `tier0.dll`.
+#[cfg(feature = "scan")]
+pub fn convars<P: Process + MemoryView>(
+    process: &mut P,
+    interfaces: &InterfaceMap,
+) -> Result<ConVarMap> {
+    let rva = interfaces
+        .get("tier0.dll")
+        .and_then(|ifaces| ifaces.get("VEngineCvar007"))
+        .ok_or_else(|| anyhow!("VEngineCvar007 interface not found"))?;
+
+    let module = process.module_by_name("tier0.dll")?;
+
+    let cvar = process.read::<Cvar>(module.base + *rva).data_part()?;
+
+    let mut result = ConVarMap::new();
+
+    for convar_ptr in cvar.convars.elements(process)? {
+        if convar_ptr.is_null() {
+            continue;
+        }
+
+        let convar = process.read_ptr(convar_ptr).data_part()?;
+        let name = process
+            .read_utf8_lossy(convar.name.address(), 128)
+            .data_part()?;
+
+        let default = process
+            .read_ptr(convar.default_value)
+            .data_part()
+            .ok()
+            .and_then(|raw| read_value(process, convar.r#type, &raw));
+
+        debug!("found convar \"{}\" (flags: {:#X})", name, convar.flags);
+
+        result.insert(
+            name,
+            ConVar {
+                flags: convar.flags,
+                flag_names: flag_names(convar.flags),
+                default,
+            },
+        );
+    }
+
+    Ok(result)
+}
+
+#[cfg(feature = "scan")]
+fn flag_names(flags: u64) -> Vec<&'static str> {
+    FLAGS
+        .iter()
+        .filter(|(flag, _)| flags & flag != 0)
+        .map(|(_, name)| *name)
+        .collect()
+}
+
+/// Interprets the raw value of a convar according to its `EConVarType`.
+#[cfg(feature = "scan")]
+fn read_value(mem: &mut impl MemoryView, r#type: i16, raw: &[u8; 0x10]) -> Option<ConVarValue> {
+    let bytes = |offset: usize| -> [u8; 8] { raw[offset..offset + 8].try_into().unwrap() };
+
+    let floats = |count: usize| {
+        ConVarValue::Components(
+            raw.chunks_exact(4)
+                .take(count)
+                .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()) as f64)
+                .collect(),
+        )
+    };
+
+    let value = match r#type {
+        0 => ConVarValue::Bool(raw[0] != 0),
+        1 => ConVarValue::Int(i16::from_le_bytes([raw[0], raw[1]]) as i64),
+        2 => ConVarValue::UInt(u16::from_le_bytes([raw[0], raw[1]]) as u64),
+        3 => ConVarValue::Int(i32::from_le_bytes(raw[..4].try_into().unwrap()) as i64),
+        4 => ConVarValue::UInt(u32::from_le_bytes(raw[..4].try_into().unwrap()) as u64),
+        5 => ConVarValue::Int(i64::from_le_bytes(bytes(0))),
+        6 => ConVarValue::UInt(u64::from_le_bytes(bytes(0))),
+        7 => ConVarValue::Float(f32::from_le_bytes(raw[..4].try_into().unwrap()) as f64),
+        8 => ConVarValue::Float(f64::from_le_bytes(bytes(0))),
+        // A `CUtlString`, which is just a pointer to the characters.
+        9 => {
+            let ptr = Address::from(u64::from_le_bytes(bytes(0)));
+
+            if ptr.is_null() {
+                ConVarValue::String(String::new())
+            } else {
+                ConVarValue::String(mem.read_utf8_lossy(ptr, 512).data_part().ok()?)
+            }
+        }
+        10 => ConVarValue::Components(raw[..4].iter().map(|c| *c as f64).collect()),
+        11 => floats(2),
+        12 | 14 => floats(3),
+        13 => floats(4),
+        _ => return None,
+    };
+
+    Some(value)
+}
+
+#[cfg(all(test, feature = "scan"))]
+mod tests {
+    use super::*;
+
+    use crate::analysis::offsets::tests::output_dir;
+
+    #[test]
+    fn flags() {
+        assert_eq!(
+            flag_names((1 << 8) | (1 << 14) | (1 << 63)),
+            ["FCVAR_NOTIFY", "FCVAR_CHEAT"]
+        );
+    }
+
+    #[test]
+    #[ignore = "needs the game running"]
+    fn sv_cheats() -> Result<()> {
+        let content = std::fs::read_to_string(output_dir().join("convars.json"))?;
+        let value: serde_json::Value = serde_json::from_str(&content)?;
+
+        let sv_cheats = value.get("sv_cheats").unwrap();
+
+        assert_eq!(sv_cheats["default"], false);
+        assert!(sv_cheats["flags"].is_u64());
+
+        Ok(())
+    }
+}
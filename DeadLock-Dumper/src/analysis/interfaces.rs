@@ -1,55 +1,171 @@
 use std::collections::BTreeMap;
 
+#[cfg(feature = "scan")]
 use anyhow::Result;
 
-use log::debug;
+#[cfg(feature = "scan")]
+use log::{debug, warn};
 
+#[cfg(feature = "scan")]
 use memflow::prelude::v1::*;
 
+#[cfg(not(feature = "scan"))]
+use super::umem;
+
+#[cfg(feature = "scan")]
 use pelite::pe64::exports::Export;
+#[cfg(feature = "scan")]
 use pelite::pe64::{Pe, PeView};
 
+#[cfg(feature = "scan")]
 use crate::memory::address;
+#[cfg(feature = "scan")]
 use crate::source2::InterfaceReg;
 
 pub type InterfaceMap = BTreeMap<String, BTreeMap<String, umem>>;
 
+/// Reads the interfaces registered by every module with a `CreateInterface` export. Exports that
+/// are forwarded to another module are followed, so its interfaces are listed under that module.
+#[cfg(feature = "scan")]
 pub fn interfaces<P: Process + MemoryView>(process: &mut P) -> Result<InterfaceMap> {
-    process
-        .module_list()?
-        .iter()
-        .filter_map(|module| {
-            let buf = process
-                .read_raw(module.base, module.size as _)
-                .data_part()
-                .ok()?;
-
-            let view = PeView::from_bytes(&buf).ok()?;
-
-            let ci_export = view
-                .exports()
-                .ok()?
-                .by()
-                .ok()?
-                .name("CreateInterface")
-                .ok()?;
-
-            match ci_export {
-                Export::Symbol(symbol) => {
-                    let list_ptr = address::resolve_rip(process, module.base + symbol).ok()?;
-                    let list_head = process.read_addr64(list_ptr).data_part().ok()?;
-
-                    return read_interfaces(process, module, list_head)
-                        .ok()
-                        .filter(|ifaces| !ifaces.is_empty())
-                        .map(|ifaces| Ok((module.name.to_string(), ifaces)));
+    let modules = process.module_list()?;
+
+    let mut result = InterfaceMap::new();
+
+    for module in &modules {
+        let Ok(buf) = process.read_raw(module.base, module.size as _).data_part() else {
+            continue;
+        };
+
+        let Ok(view) = PeView::from_bytes(&buf) else {
+            continue;
+        };
+
+        // Modules without a `CreateInterface` export simply don't expose any interfaces.
+        let (module, symbol) = match create_interface(view, "CreateInterface") {
+            Some(CreateInterface::Symbol(symbol)) => (module, symbol),
+            Some(CreateInterface::Forward {
+                module: target,
+                symbol,
+            }) => {
+                debug!(
+                    "CreateInterface of {} is forwarded to {}!{}",
+                    module.name, target, symbol
+                );
+
+                match resolve_forward(process, &modules, &target, &symbol) {
+                    Some(resolved) => resolved,
+                    None => {
+                        warn!(
+                            "failed to follow the CreateInterface export of {} to {}!{}",
+                            module.name, target, symbol
+                        );
+
+                        continue;
+                    }
                 }
-                _ => None,
             }
-        })
-        .collect()
+            None => {
+                debug!("no CreateInterface export in {}", module.name);
+
+                continue;
+            }
+        };
+
+        // The target of a forward is usually loaded as well, so it may already have been read.
+        if result.contains_key(&*module.name) {
+            continue;
+        }
+
+        let ifaces = address::resolve_rip(process, module.base + symbol)
+            .and_then(|list_ptr| process.read_addr64(list_ptr).data_part())
+            .map_err(anyhow::Error::from)
+            .and_then(|list_head| read_interfaces(process, module, list_head));
+
+        if let Some(ifaces) = ifaces.ok().filter(|ifaces| !ifaces.is_empty()) {
+            result.insert(module.name.to_string(), ifaces);
+        }
+    }
+
+    Ok(result)
+}
+
+/// Splits the version suffix off an interface name, e.g. `Source2Client002` into `Source2Client`
+/// and 2. Names without a version are returned whole.
+pub fn split_version(name: &str) -> (&str, Option<u32>) {
+    let base_name = name.trim_end_matches(|c: char| c.is_ascii_digit());
+
+    let Ok(version) = name[base_name.len()..].parse() else {
+        return (name, None);
+    };
+
+    // Some versions are separated by an underscore, as in `SchemaSystem_001`.
+    match base_name.trim_end_matches('_') {
+        "" => (name, None),
+        base_name => (base_name, Some(version)),
+    }
 }
 
+/// Where the `CreateInterface` export of a module leads.
+#[cfg(feature = "scan")]
+#[derive(Debug, PartialEq, Eq)]
+enum CreateInterface {
+    Symbol(u32),
+    /// Forwarded to the export `symbol` of the DLL `module`.
+    Forward {
+        module: String,
+        symbol: String,
+    },
+}
+
+#[cfg(feature = "scan")]
+fn create_interface(view: PeView<'_>, name: &str) -> Option<CreateInterface> {
+    let export = view
+        .exports()
+        .and_then(|exports| exports.by())
+        .and_then(|by| by.name(name))
+        .ok()?;
+
+    match export {
+        Export::Symbol(&symbol) => Some(CreateInterface::Symbol(symbol)),
+        // Forwards are written as `tier0.CreateInterface`, without the extension of the DLL.
+        Export::Forward(target) => {
+            let (module, symbol) = target.to_str().ok()?.split_once('.')?;
+
+            Some(CreateInterface::Forward {
+                module: format!("{}.dll", module),
+                symbol: symbol.to_string(),
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Finds the module a `CreateInterface` export is forwarded to, and the RVA of the function there.
+#[cfg(feature = "scan")]
+fn resolve_forward<'a>(
+    mem: &mut impl MemoryView,
+    modules: &'a [ModuleInfo],
+    module_name: &str,
+    symbol: &str,
+) -> Option<(&'a ModuleInfo, u32)> {
+    let module = modules
+        .iter()
+        .find(|module| module.name.eq_ignore_ascii_case(module_name))?;
+
+    let buf = mem
+        .read_raw(module.base, module.size as _)
+        .data_part()
+        .ok()?;
+
+    // Forwards aren't followed any further.
+    match create_interface(PeView::from_bytes(&buf).ok()?, symbol)? {
+        CreateInterface::Symbol(symbol) => Some((module, symbol)),
+        CreateInterface::Forward { .. } => None,
+    }
+}
+
+#[cfg(feature = "scan")]
 fn read_interfaces(
     mem: &mut impl MemoryView,
     module: &ModuleInfo,
@@ -82,3 +198,86 @@ fn read_interfaces(
 
     Ok(result)
 }
+
+#[cfg(all(test, feature = "scan"))]
+mod tests {
+    use super::*;
+
+    use crate::analysis::signatures::tests::build_image;
+
+    /// Builds an image whose only export, `CreateInterface`, points at `function`.
+    fn build_exporting_image(function: Option<u32>) -> Vec<u8> {
+        const DIRECTORY: usize = 0x1000;
+        const DIRECTORY_SIZE: u32 = 0x70;
+
+        let mut code = vec![0u8; 0x200];
+
+        let mut put = |offset: usize, bytes: &[u8]| {
+            code[offset..offset + bytes.len()].copy_from_slice(bytes);
+        };
+
+        // IMAGE_EXPORT_DIRECTORY
+        put(0x0C, &0x1044u32.to_le_bytes()); // Name
+        put(0x10, &1u32.to_le_bytes()); // Base
+        put(0x14, &1u32.to_le_bytes()); // NumberOfFunctions
+        put(0x18, &1u32.to_le_bytes()); // NumberOfNames
+        put(0x1C, &0x1028u32.to_le_bytes()); // AddressOfFunctions
+        put(0x20, &0x102Cu32.to_le_bytes()); // AddressOfNames
+        put(0x24, &0x1030u32.to_le_bytes()); // AddressOfNameOrdinals
+
+        // A function within the export directory is a forwarder string.
+        put(0x28, &function.unwrap_or(0x1050).to_le_bytes());
+        put(0x2C, &0x1034u32.to_le_bytes());
+        put(0x30, &0u16.to_le_bytes());
+        put(0x34, b"CreateInterface\0");
+        put(0x44, b"test.dll\0");
+        put(0x50, b"tier0.CreateInterface\0");
+
+        let mut image = build_image(&code);
+
+        // The export entry of the data directory.
+        image[0xC8..0xCC].copy_from_slice(&(DIRECTORY as u32).to_le_bytes());
+        image[0xCC..0xD0].copy_from_slice(&DIRECTORY_SIZE.to_le_bytes());
+
+        image
+    }
+
+    #[test]
+    fn versions() {
+        assert_eq!(
+            split_version("Source2Client002"),
+            ("Source2Client", Some(2))
+        );
+        assert_eq!(split_version("SchemaSystem_001"), ("SchemaSystem", Some(1)));
+        assert_eq!(split_version("VEngineCvar007"), ("VEngineCvar", Some(7)));
+        assert_eq!(
+            split_version("InputStackSystem"),
+            ("InputStackSystem", None)
+        );
+        assert_eq!(split_version("001"), ("001", None));
+    }
+
+    #[test]
+    fn forwarded_export() -> Result<()> {
+        let image = build_exporting_image(None);
+
+        assert_eq!(
+            create_interface(PeView::from_bytes(&image)?, "CreateInterface"),
+            Some(CreateInterface::Forward {
+                module: "tier0.dll".to_string(),
+                symbol: "CreateInterface".to_string(),
+            })
+        );
+
+        let image = build_exporting_image(Some(0x1100));
+
+        assert_eq!(
+            create_interface(PeView::from_bytes(&image)?, "CreateInterface"),
+            Some(CreateInterface::Symbol(0x1100))
+        );
+
+        assert_eq!(create_interface(PeView::from_bytes(&image)?, "Foo"), None);
+
+        Ok(())
+    }
+}
@@ -1,35 +1,82 @@
 pub use buttons::*;
+pub use chains::*;
+pub use convars::*;
 pub use interfaces::*;
 pub use offsets::*;
+pub use progress::*;
 pub use schemas::*;
+pub use signatures::*;
+#[cfg(feature = "scan")]
+pub use verify::*;
+pub use vtables::*;
 
-use std::any::type_name;
+use std::collections::{BTreeMap, BTreeSet};
+#[cfg(feature = "scan")]
+use std::fmt::Display;
 
+#[cfg(feature = "scan")]
 use anyhow::Result;
 
-use log::{error, info};
+#[cfg(feature = "scan")]
+use log::{error, info, warn};
 
+#[cfg(feature = "scan")]
 use memflow::prelude::v1::*;
 
+/// The address type of memflow, which the button and interface maps and the module metadata hold.
+/// It's only defined here for builds without the `scan` feature, where memflow isn't available.
+#[cfg(not(feature = "scan"))]
+#[allow(non_camel_case_types)]
+pub type umem = u64;
+
 mod buttons;
+mod chains;
+mod convars;
 mod interfaces;
 mod offsets;
+mod progress;
 mod schemas;
-
-#[derive(Debug)]
+/// The patterns and the code scanning module images for them. It doesn't depend on memflow or do
+/// any I/O, so it can scan images that were read by other means, but it's still built as part of
+/// this crate, which needs the standard library.
+mod signatures;
+#[cfg(feature = "scan")]
+mod verify;
+mod vtables;
+
+#[derive(Debug, Default)]
 pub struct AnalysisResult {
+    pub build_number: Option<u32>,
     pub buttons: ButtonMap,
+    pub convars: ConVarMap,
     pub interfaces: InterfaceMap,
+    pub match_counts: BTreeMap<String, usize>,
+    pub missing_offsets: BTreeSet<String>,
+    /// Offsets that were only found by their recovery pattern.
+    pub recovered_offsets: BTreeSet<String>,
+    /// The sections each offset was found in.
+    pub match_sections: BTreeMap<String, MatchSections>,
+    pub modules: ModuleMap,
     pub offsets: OffsetMap,
+    /// The bytes each function offset starts with, if [`ScanOptions::prologue_len`] is set.
+    pub prologues: PrologueMap,
+    /// The image each module was scanned in, if [`ScanOptions::keep_images`] is set.
+    pub images: BTreeMap<String, Vec<u8>>,
     pub schemas: SchemaMap,
+    pub vtables: VTableMap,
 }
 
-pub fn analyze_all<P: Process + MemoryView>(process: &mut P) -> Result<AnalysisResult> {
-    let buttons = analyze(process, buttons);
+#[cfg(feature = "scan")]
+pub fn analyze_all<P: Process + MemoryView>(
+    process: &mut P,
+    options: &ScanOptions,
+    progress: &dyn Progress,
+) -> Result<AnalysisResult> {
+    let buttons = analyze(process, "buttons", buttons);
 
     info!("found {} buttons", buttons.len());
 
-    let interfaces = analyze(process, interfaces);
+    let interfaces = analyze(process, "interfaces", interfaces);
 
     info!(
         "found {} interfaces across {} modules",
@@ -40,7 +87,20 @@ pub fn analyze_all<P: Process + MemoryView>(process: &mut P) -> Result<AnalysisR
         interfaces.len()
     );
 
-    let offsets = analyze(process, offsets);
+    let convars = analyze(process, "convars", |process| convars(process, &interfaces));
+
+    info!("found {} convars", convars.len());
+
+    let OffsetScan {
+        offsets,
+        missing: missing_offsets,
+        modules,
+        match_counts,
+        recovered: recovered_offsets,
+        sections: match_sections,
+        prologues,
+        images,
+    } = offsets(process, options, progress)?;
 
     info!(
         "found {} offsets across {} modules",
@@ -51,7 +111,36 @@ pub fn analyze_all<P: Process + MemoryView>(process: &mut P) -> Result<AnalysisR
         offsets.len()
     );
 
-    let schemas = analyze(process, schemas);
+    if !missing_offsets.is_empty() {
+        warn!(
+            "{} offset pattern(s) failed to match: {}",
+            missing_offsets.len(),
+            missing_offsets
+                .iter()
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    let build_number = match build_number(process, &offsets) {
+        Ok(build_number) => {
+            info!("game build number: {}", build_number);
+
+            Some(build_number)
+        }
+        Err(err) => {
+            warn!("{}", err);
+
+            None
+        }
+    };
+
+    verify_offsets(process, &offsets, options.strict)?;
+
+    // Unlike the buttons or convars, failing to read the schemas fails the dump, as empty schema
+    // files would otherwise pass for a successful one.
+    let schemas = schemas(process, &offsets, options, progress)?;
 
     let (class_count, enum_count) =
         schemas
@@ -67,22 +156,39 @@ pub fn analyze_all<P: Process + MemoryView>(process: &mut P) -> Result<AnalysisR
         schemas.len()
     );
 
+    let vtables = analyze(process, "vtables", |process| vtables(process, &schemas));
+
+    info!(
+        "found {} vtables",
+        vtables.values().map(|vtables| vtables.len()).sum::<usize>()
+    );
+
     Ok(AnalysisResult {
+        build_number,
         buttons,
+        convars,
         interfaces,
+        match_counts,
+        missing_offsets,
+        recovered_offsets,
+        match_sections,
+        modules,
         offsets,
+        prologues,
+        images,
         schemas,
+        vtables,
     })
 }
 
-fn analyze<P, F, T>(process: &mut P, f: F) -> T
+#[cfg(feature = "scan")]
+fn analyze<P, F, T, E>(process: &mut P, name: &str, f: F) -> T
 where
     P: Process + MemoryView,
-    F: FnOnce(&mut P) -> Result<T>,
+    F: FnOnce(&mut P) -> Result<T, E>,
     T: Default,
+    E: Display,
 {
-    let name = type_name::<F>();
-
     match f(process) {
         Ok(result) => result,
         Err(err) => {
@@ -92,3 +198,33 @@ where
         }
     }
 }
+
+/// Fixtures shared by the tests of the analyses and of the output they're written to.
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use super::*;
+
+    /// A `client.dll` class with `int32` fields, whose netvars are only its own fields until
+    /// they're resolved.
+    pub(crate) fn class(name: &str, parent_name: Option<&str>, fields: &[(&str, i32)]) -> Class {
+        Class {
+            name: name.to_string(),
+            module_name: "client.dll".to_string(),
+            parent_name: parent_name.map(str::to_string),
+            metadata: Vec::new(),
+            fields: fields
+                .iter()
+                .map(|(name, offset)| ClassField {
+                    name: name.to_string(),
+                    type_name: "int32".to_string(),
+                    offset: *offset,
+                    metadata: Vec::new(),
+                })
+                .collect(),
+            netvars: fields
+                .iter()
+                .map(|(name, offset)| (name.to_string(), *offset))
+                .collect(),
+        }
+    }
+}
@@ -1,146 +1,844 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+#[cfg(feature = "scan")]
+use std::thread;
 
-use anyhow::Result;
+use anyhow::{Result, anyhow, bail};
 
-use log::{debug, error};
+#[cfg(feature = "scan")]
+use log::{debug, error, warn};
 
+#[cfg(feature = "scan")]
 use memflow::prelude::v1::*;
 
+#[cfg(not(feature = "scan"))]
+use super::umem;
+
+#[cfg(feature = "scan")]
+use pelite::image::IMAGE_SCN_MEM_EXECUTE;
 use pelite::pattern;
-use pelite::pattern::{Atom, save_len};
-use pelite::pe64::{Pe, PeView, Rva};
+use pelite::pe64::Rva;
+#[cfg(feature = "scan")]
+use pelite::pe64::{Pe, PeView};
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use serde_json::Value;
+
+#[cfg(feature = "scan")]
+use rayon::prelude::*;
+
+#[cfg(feature = "scan")]
+use super::progress::Progress;
+#[cfg(feature = "scan")]
+use super::signatures::{
+    Bitness, DEFAULT_CHUNK_SIZE, ModuleEntry, OffsetFilter, collect_module_scan, image_bitness,
+    raw_image, scan_patterns, selected_modules, selected_patterns, widen_image,
+};
+use super::signatures::{
+    ExternalPatterns, MODULES, MatchSections, ModuleLocation, Offsets, PROLOGUE_SUFFIX,
+    ScanOptions, valid_captures,
+};
+
+#[cfg(feature = "scan")]
+use crate::error::DumperError;
+#[cfg(feature = "codegen")]
+use crate::output::{META_KEY, MODULE_KEY};
+
+pub type ModuleMap = BTreeMap<String, ModuleMetadata>;
+pub type OffsetMap = BTreeMap<String, BTreeMap<String, Rva>>;
+pub type PrologueMap = BTreeMap<String, BTreeMap<String, String>>;
+
+/// How much of a module is read to parse its section headers, which always fit in the first page.
+#[cfg(feature = "scan")]
+const HEADER_SIZE: usize = 0x1000;
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ModuleMetadata {
+    /// The address the module was loaded at, if it was read from the game or a cached image of it.
+    pub base: Option<umem>,
+    /// The size of the loaded module, which is `None` whenever `base` is.
+    pub size: Option<umem>,
+    /// The preferred base address from the optional header.
+    pub image_base: u64,
+    /// A hash of the `.text` section, which changes with the code even if the build number
+    /// doesn't.
+    pub text_hash: Option<String>,
+}
 
-use phf::{Map, phf_map};
+#[cfg(feature = "scan")]
+#[derive(Debug, Deserialize, Serialize)]
+struct CachedModule {
+    base: umem,
+    size: umem,
+}
 
-pub type OffsetMap = BTreeMap<String, BTreeMap<String, Rva>>;
+#[derive(Debug, Default)]
+pub struct OffsetScan {
+    pub offsets: OffsetMap,
+    /// Names of the patterns that failed to match.
+    pub missing: BTreeSet<String>,
+    pub modules: ModuleMap,
+    /// How many times each matched pattern occurs, if [`ScanOptions::count_matches`] is set.
+    pub match_counts: BTreeMap<String, usize>,
+    /// Names of the offsets that were only found by their recovery pattern.
+    pub recovered: BTreeSet<String>,
+    /// The sections each offset was found in.
+    pub sections: BTreeMap<String, MatchSections>,
+    /// The bytes each function starts with, if [`ScanOptions::prologue_len`] is set.
+    pub prologues: PrologueMap,
+    /// The image each module was scanned in, if [`ScanOptions::keep_images`] is set.
+    pub images: BTreeMap<String, Vec<u8>>,
+}
+
+#[cfg(feature = "scan")]
+impl OffsetScan {
+    /// Records the address and size each module was loaded at, which its image doesn't tell.
+    fn set_locations(&mut self, locations: &BTreeMap<String, (umem, umem)>) {
+        for (module_name, (base, size)) in locations {
+            if let Some(metadata) = self.modules.get_mut(module_name) {
+                metadata.base = Some(*base);
+                metadata.size = Some(*size);
+            }
+        }
+    }
+}
+
+/// Scans every selected module, returning the offsets that were found along with the names of the
+/// patterns that failed to match. Modules excluded by `filter` aren't read at all.
+///
+/// Modules that can't be found or read are logged and recorded as missing, unless
+/// [`ScanOptions::strict`] is set, in which case the first such module fails the whole scan.
+#[cfg(feature = "scan")]
+pub fn offsets<P: Process + MemoryView>(
+    process: &mut P,
+    options: &ScanOptions,
+    progress: &dyn Progress,
+) -> Result<OffsetScan, DumperError> {
+    let mut missing = BTreeSet::new();
+    let mut locations = BTreeMap::new();
+
+    let modules = load_modules(
+        &selected_modules(&options.filter),
+        options.strict,
+        &mut missing,
+        |module_name| {
+            let (module, buf) = read_module(process, module_name, options, progress)?;
+
+            locations.insert(
+                module_name.to_string(),
+                (module.base.to_umem(), module.size),
+            );
+
+            Ok(buf)
+        },
+    )?;
+
+    let mut scan = scan_modules(modules, options, progress)?;
+
+    scan.missing.extend(missing);
+    scan.set_locations(&locations);
+
+    Ok(scan)
+}
+
+/// Like [`offsets`], but only scans the registered modules that are actually loaded in the process
+/// rather than failing if one of them is missing.
+#[cfg(feature = "scan")]
+pub fn scan_all_modules<P: Process + MemoryView>(
+    process: &mut P,
+    options: &ScanOptions,
+    progress: &dyn Progress,
+) -> Result<OffsetScan, DumperError> {
+    let loaded = process
+        .module_list()
+        .map_err(|source| DumperError::MemoryRead {
+            module: "module list".to_string(),
+            source,
+        })?;
+
+    let modules: Vec<_> = selected_modules(&options.filter)
+        .into_iter()
+        .filter(|(module_name, ..)| {
+            let found = loaded
+                .iter()
+                .any(|module| module.name.eq_ignore_ascii_case(module_name));
+
+            if !found {
+                warn!("{} isn't loaded, skipping", module_name);
+            }
+
+            found
+        })
+        .collect();
+
+    let mut missing = BTreeSet::new();
+    let mut locations = BTreeMap::new();
+
+    let modules = load_modules(&modules, false, &mut missing, |module_name| {
+        let (module, buf) = read_module(process, module_name, options, progress)?;
+
+        locations.insert(
+            module_name.to_string(),
+            (module.base.to_umem(), module.size),
+        );
+
+        Ok(buf)
+    })?;
 
-macro_rules! pattern_map {
-    ($($module:ident => {
-        $($name:expr => $pattern:expr $(=> $callback:expr)?),+ $(,)?
-    }),+ $(,)?) => {
-        $(
-            mod $module {
-                use super::*;
-
-                pub(super) const PATTERNS: Map<
-                    &'static str,
-                    (
-                        &'static [Atom],
-                        Option<fn(&PeView, &mut BTreeMap<String, Rva>, Rva)>,
-                    ),
-                > = phf_map! {
-                    $($name => ($pattern, $($callback)?)),+
+    let mut scan = scan_modules(modules, options, progress)?;
+
+    scan.missing.extend(missing);
+    scan.set_locations(&locations);
+
+    Ok(scan)
+}
+
+/// Writes the images kept by a scan with [`ScanOptions::keep_images`] set to `dir`, alongside the
+/// base address and size of each module, so they can be re-scanned later with
+/// [`offsets_from_cache`] without access to the game. Modules are cached as they were read, so
+/// only their code is if [`ScanOptions::code_only`] was set.
+#[cfg(feature = "scan")]
+pub fn dump_modules_to_disk(
+    dir: &Path,
+    images: &BTreeMap<String, Vec<u8>>,
+    modules: &ModuleMap,
+) -> Result<(), DumperError> {
+    fs::create_dir_all(dir)?;
+
+    for (module_name, buf) in images {
+        let module = modules.get(module_name);
+
+        let cached = CachedModule {
+            base: module.and_then(|module| module.base).unwrap_or_default(),
+            size: module
+                .and_then(|module| module.size)
+                .unwrap_or(buf.len() as umem),
+        };
+
+        fs::write(dir.join(format!("{}.bin", module_name)), buf)?;
+
+        fs::write(
+            dir.join(format!("{}.json", module_name)),
+            serde_json::to_string_pretty(&cached)?,
+        )?;
+
+        debug!("cached {} ({} bytes)", module_name, buf.len());
+    }
+
+    Ok(())
+}
+
+/// Scans the module images previously written by [`dump_modules_to_disk`]. Missing images are
+/// handled the same way as modules that aren't loaded in [`offsets`].
+#[cfg(feature = "scan")]
+pub fn offsets_from_cache(
+    dir: &Path,
+    options: &ScanOptions,
+    progress: &dyn Progress,
+) -> Result<OffsetScan, DumperError> {
+    let mut missing = BTreeSet::new();
+    let mut locations = BTreeMap::new();
+
+    let modules = load_modules(
+        &selected_modules(&options.filter),
+        options.strict,
+        &mut missing,
+        |module_name| {
+            let buf = fs::read(dir.join(format!("{}.bin", module_name)))
+                .map_err(|_| DumperError::ModuleNotFound(module_name.to_string()))?;
+
+            let cached: CachedModule = serde_json::from_str(&fs::read_to_string(
+                dir.join(format!("{}.json", module_name)),
+            )?)?;
+
+            if buf.len() as umem != cached.size {
+                warn!(
+                    "cached image of {} is {} bytes, expected {}",
+                    module_name,
+                    buf.len(),
+                    cached.size
+                );
+            }
+
+            locations.insert(module_name.to_string(), (cached.base, cached.size));
+
+            Ok(buf)
+        },
+    )?;
+
+    let mut scan = scan_modules(modules, options, progress)?;
+
+    scan.missing.extend(missing);
+    scan.set_locations(&locations);
+
+    Ok(scan)
+}
+
+/// Loads patterns from a TOML file with a table for each module, mapping offset names to pattern
+/// strings in the syntax of [`pelite::pattern::parse`]:
+///
+/// ```toml
+/// ["client.dll"]
+/// dwViewMatrix = "488d0d${'} 48c1e006"
+/// ```
+///
+/// The patterns are scanned for by [`offsets`] if they're set as [`ScanOptions::patterns`].
+pub fn load_patterns(path: &Path) -> Result<ExternalPatterns> {
+    parse_patterns(&fs::read_to_string(path)?)
+}
+
+fn parse_patterns(source: &str) -> Result<ExternalPatterns> {
+    let tables: BTreeMap<String, BTreeMap<String, String>> = toml::from_str(source)?;
+
+    let mut patterns = ExternalPatterns::new();
+
+    for (module_name, table) in tables {
+        if !MODULES
+            .iter()
+            .any(|(name, ..)| name.eq_ignore_ascii_case(&module_name))
+        {
+            bail!("no patterns are scanned for in {}", module_name);
+        }
+
+        let module = patterns.entry(module_name.clone()).or_default();
+
+        for (name, source) in table {
+            let pat = pattern::parse(&source)
+                .map_err(|err| anyhow!("invalid pattern for {}: {}", name, err))?;
+
+            if !valid_captures(&[pat.as_slice()], 1, false) {
+                bail!("the pattern for {} must capture exactly one value", name);
+            }
+
+            module.insert(name, pat);
+        }
+    }
+
+    Ok(patterns)
+}
+
+/// Loads each module with `load`, skipping (and recording as missing) the ones that fail unless
+/// `strict` is set.
+#[cfg(feature = "scan")]
+fn load_modules<T>(
+    modules: &[&'static ModuleEntry],
+    strict: bool,
+    missing: &mut BTreeSet<String>,
+    mut load: impl FnMut(&str) -> Result<T, DumperError>,
+) -> Result<Vec<(&'static ModuleEntry, T)>, DumperError> {
+    let mut loaded = Vec::new();
+
+    for &entry in modules {
+        let (module_name, ..) = entry;
+
+        match load(module_name) {
+            Ok(value) => loaded.push((entry, value)),
+            Err(err) if !strict => {
+                error!("skipping {}: {}", module_name, err);
+
+                missing.insert(module_name.to_string());
+            }
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(loaded)
+}
+
+/// Reads the image of a module, retrying with exponential backoff if the read fails or some of its
+/// pages can't be read.
+///
+/// If [`ScanOptions::code_only`] is set, only the headers and the executable sections are read,
+/// unless the section headers can't be parsed.
+#[cfg(feature = "scan")]
+pub(super) fn read_module<P: Process + MemoryView>(
+    process: &mut P,
+    module_name: &str,
+    options: &ScanOptions,
+    progress: &dyn Progress,
+) -> Result<(ModuleInfo, Vec<u8>), DumperError> {
+    let module = process
+        .module_by_name(module_name)
+        .map_err(|_| DumperError::ModuleNotFound(module_name.to_string()))?;
+
+    let buf = read_with_retry(
+        module_name,
+        module.size as usize,
+        options,
+        progress,
+        |offset, chunk| process.read_raw_into(module.base + offset, chunk),
+    )?;
+
+    Ok((module, buf))
+}
+
+/// Reads the `size` bytes of the image of `module_name` with `read`, which reads the bytes at an
+/// offset into the module. Once every attempt was made, an image that was only partly read is
+/// still used, with the pages that couldn't be read zeroed, unless [`ScanOptions::strict`] is set.
+#[cfg(feature = "scan")]
+fn read_with_retry(
+    module_name: &str,
+    size: usize,
+    options: &ScanOptions,
+    progress: &dyn Progress,
+    mut read: impl FnMut(usize, &mut [u8]) -> PartialResult<()>,
+) -> Result<Vec<u8>, DumperError> {
+    let retry = options.retry;
+    let attempts = retry.attempts.max(1);
+
+    let mut attempt = 1;
+    let mut delay = retry.base_delay;
+
+    loop {
+        let err = match read_image(module_name, size, options, progress, &mut read) {
+            Ok((buf, chunks)) if chunks.is_empty() => return Ok(buf),
+            Ok((buf, chunks)) => {
+                let err = DumperError::PartialRead {
+                    module: module_name.to_string(),
+                    chunks,
                 };
 
-                pub fn offsets(view: PeView<'_>) -> BTreeMap<String, Rva> {
-                    let mut map = BTreeMap::new();
+                if attempt >= attempts && !options.strict {
+                    warn!("{}, using the pages that could be read", err);
 
-                    for (&name, (pat, callback)) in &PATTERNS {
-                        let mut save = vec![0; save_len(pat)];
+                    return Ok(buf);
+                }
 
-                        if !view.scanner().finds_code(pat, &mut save) {
-                            error!("outdated pattern: {}", name);
+                err
+            }
+            Err(source) => DumperError::MemoryRead {
+                module: module_name.to_string(),
+                source,
+            },
+        };
+
+        if attempt >= attempts {
+            return Err(err);
+        }
 
-                            continue;
-                        }
+        debug!(
+            "retrying read of {} in {:?} (attempt {}/{}): {}",
+            module_name, delay, attempt, attempts, err
+        );
 
-                        let rva = save[1];
+        thread::sleep(delay);
 
-                        map.insert(name.to_string(), rva);
+        attempt += 1;
+        delay *= 2;
+    }
+}
 
-                        if let Some(callback) = callback {
-                            callback(&view, &mut map, rva);
-                        }
-                    }
+/// Reads an image of `size` bytes with `read`, along with the offsets of the chunks that could only
+/// be partly read. If [`ScanOptions::code_only`] is set, the sections that don't contain code are
+/// left zeroed, so RIP-relative operands still resolve to the right RVA.
+#[cfg(feature = "scan")]
+fn read_image(
+    module_name: &str,
+    size: usize,
+    options: &ScanOptions,
+    progress: &dyn Progress,
+    read: &mut impl FnMut(usize, &mut [u8]) -> PartialResult<()>,
+) -> memflow::error::Result<(Vec<u8>, Vec<usize>)> {
+    if options.code_only {
+        let mut header = vec![0; HEADER_SIZE.min(size)];
+
+        let mut failed = read_chunked(module_name, size, 0, &mut header, options, progress, read)?;
+
+        match code_sections(&header) {
+            Some(sections) => {
+                let mut buf = vec![0; size];
+
+                buf[..header.len()].copy_from_slice(&header);
+
+                for (rva, section_size) in sections {
+                    let start = (rva as usize).min(buf.len());
+                    let end = (start + section_size as usize).min(buf.len());
+
+                    failed.extend(read_chunked(
+                        module_name,
+                        size,
+                        start,
+                        &mut buf[start..end],
+                        options,
+                        progress,
+                        read,
+                    )?);
+                }
+
+                return Ok((buf, failed));
+            }
+            None => debug!(
+                "failed to parse the section headers of {}, reading the whole image",
+                module_name
+            ),
+        }
+    }
+
+    let mut buf = vec![0; size];
+
+    let failed = read_chunked(module_name, size, 0, &mut buf, options, progress, read)?;
+
+    Ok((buf, failed))
+}
+
+/// Reads `buf.len()` bytes at `offset` into the `size` bytes of `module_name` with `read`, at most
+/// [`ScanOptions::chunk_size`] bytes at a time. The pages of a chunk that couldn't be read are left
+/// zeroed, the same as they would be in a single read, and the offsets of such chunks are returned.
+#[cfg(feature = "scan")]
+fn read_chunked(
+    module_name: &str,
+    size: usize,
+    offset: usize,
+    buf: &mut [u8],
+    options: &ScanOptions,
+    progress: &dyn Progress,
+    read: &mut impl FnMut(usize, &mut [u8]) -> PartialResult<()>,
+) -> memflow::error::Result<Vec<usize>> {
+    let chunk_size = options.chunk_size.unwrap_or(DEFAULT_CHUNK_SIZE).max(1);
+
+    let mut failed = Vec::new();
+
+    for (index, chunk) in buf.chunks_mut(chunk_size).enumerate() {
+        let chunk_offset = offset + index * chunk_size;
+
+        match read(chunk_offset, chunk) {
+            Err(PartialError::PartialVirtualRead(())) => failed.push(chunk_offset),
+            result => result.data_part()?,
+        }
+
+        progress.chunk_read(module_name, chunk_offset, chunk.len(), size);
+    }
+
+    Ok(failed)
+}
+
+/// The RVA and size of every executable section, read from the headers at the start of an image.
+#[cfg(feature = "scan")]
+fn code_sections(header: &[u8]) -> Option<Vec<(u32, u32)>> {
+    let view = PeView::from_bytes(header).ok()?;
+
+    let sections = view
+        .section_headers()
+        .iter()
+        .filter(|section| section.Characteristics & IMAGE_SCN_MEM_EXECUTE != 0)
+        .map(|section| (section.VirtualAddress, section.VirtualSize))
+        .collect();
+
+    Some(sections)
+}
 
-                    for (name, value) in &map {
-                        debug!(
-                            "found \"{}\" at {:#X} ({}.dll + {:#X})",
-                            name,
-                            *value as u64 + view.optional_header().ImageBase,
-                            stringify!($module),
-                            value
+#[cfg(feature = "scan")]
+fn module_metadata(view: &PeView<'_>) -> ModuleMetadata {
+    let text_hash = view
+        .section_headers()
+        .iter()
+        .find(|section| section.name() == Ok(".text"))
+        .and_then(|section| view.get_section_bytes(section).ok())
+        .map(|bytes| format!("{:016x}", fnv1a(bytes)));
+
+    ModuleMetadata {
+        image_base: view.optional_header().ImageBase,
+        text_hash,
+        ..Default::default()
+    }
+}
+
+/// 64-bit FNV-1a, which is fast and good enough to tell whether the code changed.
+#[cfg(feature = "scan")]
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xCBF29CE484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001B3)
+    })
+}
+
+/// Scans the module images, and the patterns within each of them, in parallel. How much faster
+/// that is depends on the number of cores, which `cargo bench --bench scan -- offsets_from_cache`
+/// measures by comparing the scan on a single thread against the scan on every core.
+#[cfg(feature = "scan")]
+fn scan_modules(
+    modules: Vec<(&ModuleEntry, Vec<u8>)>,
+    options: &ScanOptions,
+    progress: &dyn Progress,
+) -> Result<OffsetScan, DumperError> {
+    progress.scan_started(modules.len());
+
+    let results = modules
+        .par_iter()
+        .map(
+            |((module_name, patterns, _, aliases), buf)| -> Result<_, DumperError> {
+                #[cfg(feature = "tracing")]
+                let span = tracing::info_span!(
+                    "scan_module",
+                    module = *module_name,
+                    found = tracing::field::Empty,
+                    missing = tracing::field::Empty,
+                );
+
+                #[cfg(feature = "tracing")]
+                let _entered = span.enter();
+
+                let converted;
+
+                let view = match PeView::from_bytes(buf) {
+                    Ok(view) => view,
+                    Err(_) if image_bitness(buf) == Some(Bitness::Pe32) => {
+                        debug!("{} is a 32-bit image, widening its headers", module_name);
+
+                        converted = widen_image(buf).map_err(|err| {
+                            DumperError::PeParse(format!("{}: {}", module_name, err))
+                        })?;
+
+                        PeView::from_bytes(&converted).map_err(|err| {
+                            DumperError::PeParse(format!("{}: {}", module_name, err))
+                        })?
+                    }
+                    Err(err) => {
+                        warn!(
+                            "failed to parse {} ({}), scanning its raw bytes instead",
+                            module_name, err
                         );
+
+                        converted = raw_image(buf).ok_or_else(|| {
+                            DumperError::PeParse(format!("{}: {}", module_name, err))
+                        })?;
+
+                        PeView::from_bytes(&converted).map_err(|err| {
+                            DumperError::PeParse(format!("{}: {}", module_name, err))
+                        })?
                     }
+                };
 
-                    map
-                }
-            }
-        )+
-    };
+                let metadata = module_metadata(&view);
+
+                let selected = selected_patterns(module_name, patterns, options);
+
+                progress.module_started(module_name, selected.len());
+
+                // Every pattern is scanned for independently, so they're matched in parallel.
+                let results: Vec<_> = selected
+                    .into_par_iter()
+                    .map(|(name, entry)| {
+                        let (patterns, slot, _, recovery) = &entry;
+
+                        // The patterns are scanned for on other threads, so the module's span has to
+                        // be named as their parent explicitly.
+                        #[cfg(feature = "tracing")]
+                        let _entered =
+                            tracing::debug_span!(parent: &span, "scan_pattern", pattern = name)
+                                .entered();
+
+                        let found = scan_patterns(&view, name, patterns, *slot, *recovery, options);
+
+                        #[cfg(feature = "tracing")]
+                        match &found {
+                            Some(found) => tracing::debug!(
+                                module = *module_name,
+                                pattern = name,
+                                rva = found.value,
+                                site = found.site,
+                                recovered = found.recovered,
+                                "pattern matched"
+                            ),
+                            None => tracing::warn!(
+                                module = *module_name,
+                                pattern = name,
+                                "pattern not found"
+                            ),
+                        }
+
+                        progress.pattern_resolved(module_name, name, found.is_some());
+
+                        (name, entry, found)
+                    })
+                    .collect();
+
+                let found = results.iter().filter(|(.., found)| found.is_some()).count();
+
+                progress.module_finished(module_name, found, results.len() - found);
+
+                #[cfg(feature = "tracing")]
+                span.record("found", found)
+                    .record("missing", results.len() - found);
+
+                let module_scan =
+                    collect_module_scan(&view, module_name, results, aliases, options);
+
+                Ok((module_name.to_string(), module_scan, metadata))
+            },
+        )
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut scan = OffsetScan::default();
+
+    for (module_name, module_scan, metadata) in results {
+        scan.offsets
+            .insert(module_name.clone(), module_scan.offsets);
+        scan.missing.extend(module_scan.missing);
+        scan.match_counts.extend(module_scan.match_counts);
+        scan.recovered.extend(module_scan.recovered);
+        scan.sections.extend(module_scan.sections);
+        scan.prologues
+            .insert(module_name.clone(), module_scan.prologues);
+        scan.modules.insert(module_name, metadata);
+    }
+
+    if options.keep_images {
+        scan.images = modules
+            .into_iter()
+            .map(|((module_name, ..), buf)| (module_name.to_string(), buf))
+            .collect();
+    }
+
+    Ok(scan)
 }
 
-pattern_map! {
-    // Deadlock patterns (client.dll) - Updated from UC thread page 55
-    client => {
-        // 48 89 35 ?? ?? ?? ?? 48 85 F6
-        "dwEntityList" => pattern!("488935${'} 4885f6") => None,
-        // 48 8B 35 ?? ?? ?? ?? 4C 89 B4 24 ?? ?? ?? ?? 4C 89 BC 24
-        "dwGameEntitySystem" => pattern!("488b35${'} 4c89b424???? 4c89bc24") => None,
-        // 48 3B 35
-        "dwLocalPlayerController" => pattern!("483b35${'}") => None,
-        // 49 8D 87 ?? ?? ?? ?? 4D 69 F4
-        // Note: This is register-relative (LEA r8, [r15+disp]), not RIP-relative
-        // So we capture the displacement directly with u4 instead of ${'}
-        "dwViewMatrix" => pattern!("498d87 u4 4d69f4") => None,
-        // 48 8D 3D ?? ?? ?? ?? 8B D9
-        "dwCCitadelCameraManager" => pattern!("488d3d${'} 8bd9") => None,
-        // 48 8B 0D ?? ?? ?? ?? 4C 8D 44 24 ?? E8 ?? ?? ?? ?? E8
-        "dwGameTraceManager" => pattern!("488b0d${'} 4c8d4424? e8???? e8") => None,
-        // E8 ?? ?? ?? ?? 48 85 C0 74 ?? 48 8B 40 ?? 48 8D 0D
-        "fnGetCmd" => pattern!("e8${'} 4885c0 74? 488b40? 488d0d") => None,
-        // E8 ?? ?? ?? ?? 48 8B 53 ?? 48 3B D5
-        "fnDecodeNetworkEntities" => pattern!("e8${'} 488b53? 483bd5") => None,
-        // 4C 8D 35 ?? ?? ?? ?? 0F 28 45
-        "dwSchemas" => pattern!("4c8d35${'} 0f2845") => None,
-        // 48 8D 05 ?? ?? ?? ?? C3 CC CC CC CC CC CC CC CC 48 8D 05 ?? ?? ?? ??
-        "dwMaterialSystem" => pattern!("488d05${'} c3 cccccccccccccccc 488d05????") => None,
-    },
-    engine2 => {
-        "dwBuildNumber" => pattern!("8905${'} 488d0d${} ff15${} 488b0d") => None,
-        "dwNetworkGameClient" => pattern!("48893d${'} ff87") => None,
-        "dwNetworkGameClient_clientTickCount" => pattern!("8b81u4 c3 cccccccccccccccccc 8b81${} c3 cccccccccccccccccc 83b9") => None,
-        "dwNetworkGameClient_deltaTick" => pattern!("4c8db7u4 4c897c24") => None,
-        "dwNetworkGameClient_isBackgroundMap" => pattern!("0fb681u4 c3 cccccccccccccccc 0fb681${} c3 cccccccccccccccc 4053") => None,
-        "dwNetworkGameClient_localPlayer" => pattern!("428b94d3u4 5b 49ffe3 32c0 5b c3 cccccccccccccccc 4053") => None,
-        "dwNetworkGameClient_maxClients" => pattern!("8b81u4 c3????????? 8b81[4] c3????????? 8b81") => None,
-        "dwNetworkGameClient_serverTickCount" => pattern!("8b81u4 c3 cccccccccccccccccc 83b9") => None,
-        "dwNetworkGameClient_signOnState" => pattern!("448b81u4 488d0d") => None,
-        "dwWindowHeight" => pattern!("8b05${'} 8903") => None,
-        "dwWindowWidth" => pattern!("8b05${'} 8907") => None,
-    },
-    input_system => {
-        "dwInputSystem" => pattern!("488d05${'} c3 cccccccccccccccc 4053") => None,
-    },
+#[cfg(feature = "codegen")]
+impl Offsets {
+    /// Loads an `offsets.json` written with the default offset names and as RVAs. The registered
+    /// offsets that are missing from it, e.g. because their pattern didn't match, are `None`.
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
 }
 
-pub fn offsets<P: Process + MemoryView>(process: &mut P) -> Result<OffsetMap> {
-    let mut map = BTreeMap::new();
+/// An [`OffsetMap`] that can be compared and serialized on its own. It's written as a plain map of
+/// module names to offset names to RVAs, and can be read back from an `offsets.json` written as
+/// RVAs, whose metadata and module locations are skipped.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(transparent)]
+pub struct RvaMap(pub OffsetMap);
+
+impl RvaMap {
+    #[cfg(feature = "codegen")]
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
 
-    let modules: [(&str, fn(PeView) -> BTreeMap<String, u32>); 3] = [
-        ("client.dll", client::offsets),
-        ("engine2.dll", engine2::offsets),
-        ("inputsystem.dll", input_system::offsets),
-    ];
+    /// The RVA of the offset `name` in `module_name`, if it was found.
+    pub fn get(&self, module_name: &str, name: &str) -> Option<Rva> {
+        self.0.get(module_name)?.get(name).copied()
+    }
+}
 
-    for (module_name, offsets) in &modules {
-        let module = process.module_by_name(module_name)?;
+impl From<OffsetMap> for RvaMap {
+    fn from(offsets: OffsetMap) -> Self {
+        Self(offsets)
+    }
+}
 
-        let buf = process
-            .read_raw(module.base, module.size as _)
-            .data_part()?;
+#[cfg(feature = "codegen")]
+impl<'de> Deserialize<'de> for RvaMap {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
 
-        let view = PeView::from_bytes(&buf)?;
+        parse_offsets(&value)
+            .map(|(offsets, ..)| Self(offsets))
+            .map_err(D::Error::custom)
+    }
+}
+
+/// Reads back an `offsets.json` written as RVAs, along with the location of each module and the
+/// build number if they were written.
+#[cfg(feature = "codegen")]
+pub fn load_offsets(path: &Path) -> Result<(OffsetMap, ModuleMap, Option<u32>)> {
+    parse_offsets(&serde_json::from_str(&fs::read_to_string(path)?)?)
+}
+
+#[cfg(feature = "codegen")]
+fn parse_offsets(value: &Value) -> Result<(OffsetMap, ModuleMap, Option<u32>)> {
+    let build_number = value
+        .get("build_number")
+        .and_then(Value::as_u64)
+        .and_then(|build_number| build_number.try_into().ok());
+
+    let mut offsets = OffsetMap::new();
+    let mut modules = ModuleMap::new();
+
+    for (module_name, module) in value.as_object().into_iter().flatten() {
+        if module_name == META_KEY {
+            continue;
+        }
 
-        map.insert(module_name.to_string(), offsets(view));
+        let Some(module) = module.as_object() else {
+            continue;
+        };
+
+        let mut module_offsets = BTreeMap::new();
+
+        for (name, value) in module {
+            match name.as_str() {
+                MODULE_KEY => {
+                    let location: ModuleLocation = serde_json::from_value(value.clone())?;
+
+                    modules.insert(
+                        module_name.clone(),
+                        ModuleMetadata {
+                            base: location.base,
+                            size: location.size,
+                            image_base: location.image_base,
+                            text_hash: None,
+                        },
+                    );
+                }
+                // Prologues are written next to the offsets, but aren't RVAs.
+                _ if name.ends_with(PROLOGUE_SUFFIX) && value.is_string() => {}
+                _ => {
+                    let rva = value
+                        .as_u64()
+                        .and_then(|rva| Rva::try_from(rva).ok())
+                        .ok_or_else(|| anyhow!("{} in {} isn't an RVA", name, module_name))?;
+
+                    module_offsets.insert(name.clone(), rva);
+                }
+            }
+        }
+
+        offsets.insert(module_name.clone(), module_offsets);
     }
 
-    Ok(map)
+    Ok((offsets, modules, build_number))
+}
+
+/// Returns the name of the offset found at `rva` in `module_name`, if any.
+pub fn name_for_rva<'a>(map: &'a OffsetMap, module_name: &str, rva: Rva) -> Option<&'a str> {
+    map.get(module_name)?
+        .iter()
+        .find(|(_, value)| **value == rva)
+        .map(|(name, _)| name.as_str())
+}
+
+/// Like [`name_for_rva`], but for an absolute address in a module loaded at `module_base`.
+pub fn name_for_address<'a>(
+    map: &'a OffsetMap,
+    module_name: &str,
+    module_base: u64,
+    address: u64,
+) -> Option<&'a str> {
+    let rva = Rva::try_from(address.checked_sub(module_base)?).ok()?;
+
+    name_for_rva(map, module_name, rva)
 }
 
-#[cfg(test)]
-mod tests {
+#[cfg(feature = "scan")]
+pub fn build_number<P: Process + MemoryView>(process: &mut P, offsets: &OffsetMap) -> Result<u32> {
+    offsets
+        .iter()
+        .find_map(|(module_name, offsets)| {
+            let module = process.module_by_name(module_name).ok()?;
+            let offset = offsets.get("dwBuildNumber")?;
+
+            process.read::<u32>(module.base + *offset).data_part().ok()
+        })
+        .ok_or(anyhow!("failed to read build number"))
+}
+
+#[cfg(all(test, feature = "scan"))]
+pub(super) mod tests {
     use std::fs;
+    use std::path::PathBuf;
     use std::sync::Once;
 
     use serde_json::Value;
@@ -149,7 +847,13 @@ mod tests {
 
     use super::*;
 
+    use crate::analysis::signatures::tests::build_image;
+    use crate::analysis::{ReadRetry, pattern_sources};
+    use crate::reader::EntityReader;
+    use crate::source2::SchemaSystem;
+
     #[test]
+    #[ignore = "needs the game running"]
     fn build_number() -> Result<()> {
         let mut process = setup()?;
 
@@ -165,6 +869,22 @@ mod tests {
     }
 
     #[test]
+    #[ignore = "needs the game running"]
+    fn buttons() -> Result<()> {
+        let content = fs::read_to_string(output_dir().join("buttons.json"))?;
+        let value: Value = serde_json::from_str(&content)?;
+
+        let buttons = value.get("client.dll").and_then(Value::as_object).unwrap();
+
+        for name in ["attack", "back", "forward", "jump", "left", "right"] {
+            assert!(buttons.contains_key(name), "missing button \"{}\"", name);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    #[ignore = "needs the game running"]
     fn global_vars() -> Result<()> {
         let mut process = setup()?;
 
@@ -186,23 +906,91 @@ mod tests {
     }
 
     #[test]
-    fn local_controller() -> Result<()> {
+    #[ignore = "needs the game running"]
+    fn entity_list() -> Result<()> {
         let mut process = setup()?;
 
         let client_base = process.module_by_name("client.dll")?.base;
 
-        let local_controller_offset = read_offset("client.dll", "dwLocalPlayerController").unwrap();
+        let entity_list_offset = read_offset("client.dll", "dwEntityList").unwrap();
+        let chunk_size = read_offset("client.dll", "dwEntityList_chunkSize").unwrap();
+        let entry_size = read_offset("client.dll", "dwEntityList_entrySize").unwrap();
 
-        let player_name_offset =
-            read_class_field("client.dll", "CBasePlayerController", "m_iszPlayerName").unwrap();
+        let entity_list: u64 = process.read(client_base + entity_list_offset).data_part()?;
 
-        let local_controller: u64 = process
-            .read(client_base + local_controller_offset)
-            .data_part()?;
+        // The chunk pointers start right after the entity system's vtable and padding.
+        let chunk: u64 = process.read((entity_list + 0x10).into()).data_part()?;
 
-        let player_name = process
-            .read_utf8((local_controller + player_name_offset).into(), 128)
-            .data_part()?;
+        assert_ne!(chunk, 0);
+
+        let mut count = 0;
+
+        for index in 0..chunk_size {
+            let entity: u64 = process
+                .read((chunk + index * entry_size).into())
+                .data_part()?;
+
+            if entity != 0 {
+                count += 1;
+            }
+        }
+
+        debug!("[entity list] {} entities in the first chunk", count);
+
+        assert!(count > 0);
+
+        Ok(())
+    }
+
+    #[test]
+    #[ignore = "needs the game running"]
+    fn entity_system_bounds() -> Result<()> {
+        let mut process = setup()?;
+
+        let identity_count = read_offset("client.dll", "dwGameEntitySystem_identityCount").unwrap();
+
+        let mut reader = EntityReader::load(&mut process, &output_dir())?;
+
+        // Every chunk up to the last identity can be looked up, whether it's allocated or not.
+        let entities = (0..identity_count)
+            .map(|index| reader.entity(index))
+            .collect::<Result<Vec<_>>>()?;
+
+        debug!(
+            "[entity system] {} of {} identities in use",
+            entities.iter().flatten().count(),
+            identity_count
+        );
+
+        assert!(entities.iter().any(Option::is_some));
+
+        Ok(())
+    }
+
+    #[test]
+    #[ignore = "needs the game running"]
+    fn player_controllers() -> Result<()> {
+        let mut process = setup()?;
+
+        let mut reader = EntityReader::load(&mut process, &output_dir())?;
+
+        let controllers = reader.player_controllers()?;
+
+        debug!("[player controllers] {} connected", controllers.len());
+
+        // Only passes in a match, the main menu doesn't have any players.
+        assert!(controllers.len() > 1);
+        assert!(controllers.contains(&reader.local_controller()?));
+
+        Ok(())
+    }
+
+    #[test]
+    #[ignore = "needs the game running"]
+    fn local_controller() -> Result<()> {
+        let mut process = setup()?;
+
+        let player_name = EntityReader::load(&mut process, &output_dir())?.local_player_name()?;
 
         debug!("[local controller] name: \"{}\"", player_name);
 
@@ -210,48 +998,585 @@ mod tests {
     }
 
     #[test]
+    #[ignore = "needs the game running"]
     fn local_pawn() -> Result<()> {
-        #[derive(Pod)]
-        #[repr(C)]
-        struct Vector3D {
-            x: f32,
-            y: f32,
-            z: f32,
+        let mut process = setup()?;
+
+        let [x, y, z] = EntityReader::load(&mut process, &output_dir())?.local_origin()?;
+
+        debug!("[local pawn] origin: {:.2}, y: {:.2}, z: {:.2}", x, y, z);
+
+        Ok(())
+    }
+
+    #[test]
+    #[ignore = "needs the game running"]
+    fn view_angles() -> Result<()> {
+        let mut process = setup()?;
+
+        let [pitch, yaw, roll] = EntityReader::load(&mut process, &output_dir())?.view_angles()?;
+
+        debug!(
+            "[view angles] pitch: {:.2}, yaw: {:.2}, roll: {:.2}",
+            pitch, yaw, roll
+        );
+
+        assert!((-90.0..=90.0).contains(&pitch));
+        assert!((-180.0..=180.0).contains(&yaw));
+
+        Ok(())
+    }
+
+    #[test]
+    #[ignore = "needs the game running"]
+    fn game_rules() -> Result<()> {
+        let mut process = setup()?;
+
+        let mut reader = EntityReader::load(&mut process, &output_dir())?;
+
+        let game_rules = reader.game_rules()?;
+
+        assert!(!game_rules.is_null());
+
+        let paused_ticks: i32 = reader.field(game_rules, "C_GameRules", "m_nTotalPausedTicks")?;
+        let paused: u8 = reader.field(game_rules, "C_GameRules", "m_bGamePaused")?;
+
+        debug!(
+            "[game rules] paused: {}, total paused ticks: {}",
+            paused, paused_ticks
+        );
+
+        assert!(paused_ticks >= 0);
+        assert!(paused <= 1);
+
+        Ok(())
+    }
+
+    #[test]
+    #[ignore = "needs the game running"]
+    fn local_pawn_offset() -> Result<()> {
+        let offset = read_offset("client.dll", "dwLocalPlayerPawn");
+
+        assert!(offset.is_some_and(|offset| offset != 0));
+
+        Ok(())
+    }
+
+    #[test]
+    #[ignore = "needs the game running"]
+    fn local_pawn_handle() -> Result<()> {
+        let mut process = setup()?;
+
+        let mut reader = EntityReader::load(&mut process, &output_dir())?;
+
+        let controller = reader.local_controller()?;
+        let pawn = reader.local_pawn()?;
+
+        // The low bits of an entity handle are the index of the entity it refers to.
+        let handle: u32 = reader.field(controller, "CBasePlayerController", "m_hPawn")?;
+
+        assert!(!pawn.is_null());
+        assert_eq!(reader.entity((handle & 0x7FFF) as u64)?, Some(pawn));
+
+        Ok(())
+    }
+
+    #[test]
+    fn cached_modules() -> Result<()> {
+        let temp = tempfile::tempdir()?;
+        let dir = temp.path();
+
+        for (module_name, ..) in MODULES {
+            cache_module(dir, module_name)?;
         }
 
+        let strict_options = ScanOptions {
+            strict: true,
+            ..Default::default()
+        };
+
+        let scan = offsets_from_cache(dir, &strict_options, &())?;
+
+        assert_eq!(scan.offsets.len(), MODULES.len());
+        assert_eq!(scan.modules["client.dll"].image_base, 0x180000000);
+        assert_eq!(scan.modules["client.dll"].base, Some(0x180000000));
+        assert_eq!(
+            scan.modules["client.dll"].size,
+            Some(build_image(&[0xCC; 0x20]).len() as umem)
+        );
+        assert!(scan.missing.contains("dwEntityList"));
+
+        let filter = OffsetFilter {
+            modules: vec!["client.dll".to_string()],
+            names: vec!["dwEntity*".to_string()],
+        };
+
+        let scan = offsets_from_cache(
+            dir,
+            &ScanOptions {
+                filter,
+                keep_images: true,
+                ..strict_options
+            },
+            &(),
+        )?;
+
+        assert!(scan.offsets.contains_key("client.dll"));
+        assert!(!scan.offsets.contains_key("engine2.dll"));
+        assert!(scan.missing.contains("dwEntityList"));
+        assert!(!scan.missing.contains("dwViewMatrix"));
+
+        // The kept images are cached without reading the modules again.
+        let recached = dir.join("recached");
+
+        dump_modules_to_disk(&recached, &scan.images, &scan.modules)?;
+
+        assert_eq!(
+            fs::read(recached.join("client.dll.bin"))?,
+            build_image(&[0xCC; 0x20])
+        );
+        assert!(!recached.join("engine2.dll.bin").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn missing_module() -> Result<()> {
+        let temp = tempfile::tempdir()?;
+        let dir = temp.path();
+
+        // Only cache the client, as if the other modules hadn't been loaded yet.
+        cache_module(dir, "client.dll")?;
+
+        let scan = offsets_from_cache(dir, &ScanOptions::default(), &())?;
+
+        assert_eq!(scan.offsets.len(), 1);
+        assert!(scan.offsets.contains_key("client.dll"));
+        assert!(scan.missing.contains("engine2.dll"));
+
+        assert!(matches!(
+            offsets_from_cache(
+                dir,
+                &ScanOptions {
+                    strict: true,
+                    ..Default::default()
+                },
+                &()
+            ),
+            Err(DumperError::ModuleNotFound(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn strict_missing_module() -> Result<()> {
+        let os = memflow_native::create_os(&OsArgs::default(), LibArc::default())?;
+
+        // The test itself doesn't load any of the game's modules.
+        let mut process = os.into_process_by_pid(std::process::id())?;
+
+        let options = ScanOptions {
+            strict: true,
+            ..Default::default()
+        };
+
+        let err = crate::analysis::analyze_all(&mut process, &options, &()).unwrap_err();
+
+        assert!(matches!(
+            err.downcast_ref::<DumperError>(),
+            Some(DumperError::ModuleNotFound(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn progress() -> Result<()> {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        #[derive(Default)]
+        struct Counter {
+            modules: AtomicUsize,
+            patterns: AtomicUsize,
+            missing: AtomicUsize,
+        }
+
+        impl Progress for Counter {
+            fn module_started(&self, _module_name: &str, _patterns: usize) {
+                self.modules.fetch_add(1, Ordering::Relaxed);
+            }
+
+            fn pattern_resolved(&self, _module_name: &str, _name: &str, _found: bool) {
+                self.patterns.fetch_add(1, Ordering::Relaxed);
+            }
+
+            fn module_finished(&self, _module_name: &str, _found: usize, missing: usize) {
+                self.missing.fetch_add(missing, Ordering::Relaxed);
+            }
+        }
+
+        let temp = tempfile::tempdir()?;
+        let dir = temp.path();
+
+        cache_module(dir, "client.dll")?;
+
+        let counter = Counter::default();
+
+        let options = ScanOptions {
+            filter: OffsetFilter {
+                modules: vec!["client.dll".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        offsets_from_cache(dir, &options, &counter)?;
+
+        let patterns = pattern_sources()
+            .values()
+            .filter(|(module_name, _)| *module_name == "client.dll")
+            .count();
+
+        assert_eq!(counter.modules.into_inner(), 1);
+        assert_eq!(counter.patterns.into_inner(), patterns);
+        assert_eq!(counter.missing.into_inner(), patterns);
+
+        Ok(())
+    }
+
+    #[test]
+    fn external_patterns() -> Result<()> {
+        // lea rax, [rip + disp]; ret; int3 * 8; push rbx
+        let image = build_image(&[
+            0x48, 0x8D, 0x05, 0x10, 0x00, 0x00, 0x00, 0xC3, 0xCC, 0xCC, 0xCC, 0xCC, 0xCC, 0xCC,
+            0xCC, 0xCC, 0x40, 0x53,
+        ]);
+
+        let options = ScanOptions {
+            patterns: parse_patterns(
+                r#"
+                ["inputsystem.dll"]
+                dwInputSystem = "488d05${'} c3"
+                dwInputDisplacement = "488d05u4 c3"
+                "#,
+            )?,
+            ..Default::default()
+        };
+
+        let scan = crate::analysis::input_system::offsets(PeView::from_bytes(&image)?, &options);
+
+        assert_eq!(scan.offsets.get("dwInputSystem"), Some(&0x1017));
+        assert_eq!(scan.offsets.get("dwInputDisplacement"), Some(&0x10));
+
+        let err = parse_patterns("[\"client.dll\"]\ndwBroken = \"48 zz\"").unwrap_err();
+
+        assert!(err.to_string().contains("dwBroken"));
+
+        assert!(parse_patterns("[\"client.dll\"]\ndwNoCapture = \"488b05\"").is_err());
+        assert!(parse_patterns("[\"unknown.dll\"]\ndwFoo = \"488b05${'}\"").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn raw_module() -> Result<()> {
+        // lea rax, [rip + 0x10]; ret; int3 * 8; push rbx
+        let mut image = build_image(
+            &[0x48, 0x8D, 0x05, 0x10, 0x00, 0x00, 0x00, 0xC3]
+                .into_iter()
+                .chain([0xCC; 8])
+                .chain([0x40, 0x53])
+                .collect::<Vec<_>>(),
+        );
+
+        // A manually mapped module whose header was wiped.
+        image[..0x200].fill(0);
+
+        assert!(PeView::from_bytes(&image).is_err());
+
+        let entry = MODULES
+            .iter()
+            .find(|(name, ..)| *name == "inputsystem.dll")
+            .unwrap();
+
+        let scan = scan_modules(vec![(entry, image)], &ScanOptions::default(), &())?;
+
+        assert_eq!(
+            scan.offsets["inputsystem.dll"].get("dwInputSystem"),
+            Some(&0x1017)
+        );
+
+        // Too small to hold anything past the header.
+        assert!(scan_modules(vec![(entry, vec![0; 0x800])], &ScanOptions::default(), &()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    #[ignore = "needs the game running"]
+    fn chunked_read() -> Result<()> {
+        let mut process = setup()?;
+
+        let options = ScanOptions {
+            chunk_size: Some(0x1000),
+            ..Default::default()
+        };
+
+        let (module, chunked) = read_module(&mut process, "engine2.dll", &options, &())?;
+
+        let whole = process
+            .read_raw(module.base, module.size as _)
+            .data_part()?;
+
+        // Only the code is compared, as the data can change between the reads.
+        assert_eq!(chunked.len(), whole.len());
+        assert_eq!(
+            module_metadata(&PeView::from_bytes(&chunked)?).text_hash,
+            module_metadata(&PeView::from_bytes(&whole)?).text_hash
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn partial_read() -> Result<()> {
+        let image = build_image(&[0xCC; 0x20]);
+
+        let options = ScanOptions {
+            chunk_size: Some(0x1000),
+            retry: ReadRetry {
+                attempts: 3,
+                base_delay: std::time::Duration::ZERO,
+            },
+            ..Default::default()
+        };
+
+        // Fails to read the page of the code `failures` times, as a flaky connector would.
+        fn flaky(
+            image: &[u8],
+            mut failures: u32,
+        ) -> impl FnMut(usize, &mut [u8]) -> PartialResult<()> + '_ {
+            move |offset, chunk| {
+                if offset == 0x1000 && failures > 0 {
+                    failures -= 1;
+
+                    return Err(PartialError::PartialVirtualRead(()));
+                }
+
+                chunk.copy_from_slice(&image[offset..offset + chunk.len()]);
+
+                Ok(())
+            }
+        }
+
+        let buf = read_with_retry("client.dll", image.len(), &options, &(), flaky(&image, 2))?;
+
+        assert_eq!(buf, image);
+
+        // Once every attempt failed, the partial image is only used outside of strict mode.
+        let buf = read_with_retry("client.dll", image.len(), &options, &(), flaky(&image, 3))?;
+
+        assert_eq!(buf[..0x1000], image[..0x1000]);
+        assert!(buf[0x1000..].iter().all(|&b| b == 0));
+
+        let strict_options = ScanOptions {
+            strict: true,
+            ..options
+        };
+
+        assert!(matches!(
+            read_with_retry(
+                "client.dll",
+                image.len(),
+                &strict_options,
+                &(),
+                flaky(&image, 3)
+            ),
+            Err(DumperError::PartialRead { chunks, .. }) if chunks == [0x1000]
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn code_sections() -> Result<()> {
+        let image = build_image(&[0xCC; 0x20]);
+
+        assert_eq!(
+            super::code_sections(&image[..HEADER_SIZE]),
+            Some(vec![(0x1000, 0x1000)])
+        );
+
+        assert_eq!(super::code_sections(&[0; 0x40]), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn text_hash() -> Result<()> {
+        assert_eq!(fnv1a(b""), 0xCBF29CE484222325);
+        assert_eq!(fnv1a(b"a"), 0xAF63DC4C8601EC8C);
+
+        let hash = |code: &[u8]| -> Result<Option<String>> {
+            let image = build_image(code);
+
+            Ok(module_metadata(&PeView::from_bytes(&image)?).text_hash)
+        };
+
+        let original = hash(&[0x48, 0x8B, 0x05, 0x00, 0x01, 0x00, 0x00])?;
+
+        assert_eq!(original.as_ref().map(String::len), Some(16));
+        assert_eq!(hash(&[0x48, 0x8B, 0x05, 0x00, 0x01, 0x00, 0x00])?, original);
+        assert_ne!(hash(&[0x48, 0x8B, 0x05, 0x00, 0x02, 0x00, 0x00])?, original);
+
+        Ok(())
+    }
+
+    #[test]
+    fn typed_offsets() -> Result<()> {
+        let temp = tempfile::tempdir()?;
+        let path = temp.path().join("offsets.json");
+
+        let mut value = serde_json::json!({ "version": 1, "build_number": null });
+
+        for (name, (module_name, _)) in pattern_sources() {
+            value[module_name][name] = 0x1000.into();
+        }
+
+        value["client.dll"]["dwEntityList"] = 0x1A2B3C.into();
+        value["client.dll"]["dwLocalPlayerPawn"] = 0x1B80.into();
+        value["client.dll"]["__module__"] = serde_json::json!({
+            "base": 0x7FF800000000u64,
+            "size": 0x2000000,
+            "image_base": 0x180000000u64,
+        });
+
+        fs::write(&path, value.to_string())?;
+
+        let offsets = Offsets::load(&path)?;
+
+        assert_eq!(
+            offsets.client.module,
+            Some(crate::analysis::ModuleLocation {
+                base: Some(0x7FF800000000),
+                size: Some(0x2000000),
+                image_base: 0x180000000,
+            })
+        );
+        assert_eq!(offsets.engine2.module, None);
+
+        assert_eq!(offsets.client.dw_entity_list, Some(0x1A2B3C));
+        assert_eq!(offsets.engine2.dw_build_number, Some(0x1000));
+        assert_eq!(offsets.client.other.get("dwLocalPlayerPawn"), Some(&0x1B80));
+
+        // A stale pattern only leaves its own offset out.
+        value["engine2.dll"]
+            .as_object_mut()
+            .unwrap()
+            .remove("dwBuildNumber");
+
+        fs::write(&path, value.to_string())?;
+
+        let offsets = Offsets::load(&path)?;
+
+        assert_eq!(offsets.engine2.dw_build_number, None);
+        assert_eq!(offsets.client.dw_entity_list, Some(0x1A2B3C));
+
+        Ok(())
+    }
+
+    #[test]
+    fn reverse_lookup() {
+        let map = OffsetMap::from([(
+            "client.dll".to_string(),
+            BTreeMap::from([
+                ("dwEntityList".to_string(), 0x1A2B3C),
+                ("dwViewMatrix".to_string(), 0x1B80),
+            ]),
+        )]);
+
+        assert_eq!(
+            name_for_rva(&map, "client.dll", 0x1A2B3C),
+            Some("dwEntityList")
+        );
+        assert_eq!(name_for_rva(&map, "client.dll", 0x10), None);
+        assert_eq!(name_for_rva(&map, "engine2.dll", 0x1B80), None);
+
+        assert_eq!(
+            name_for_address(&map, "client.dll", 0x7FF800000000, 0x7FF800001B80),
+            Some("dwViewMatrix")
+        );
+        assert_eq!(
+            name_for_address(&map, "client.dll", 0x7FF800000000, 0x1B80),
+            None
+        );
+    }
+
+    #[test]
+    #[ignore = "needs the game running"]
+    fn prediction() -> Result<()> {
         let mut process = setup()?;
 
         let client_base = process.module_by_name("client.dll")?.base;
 
-        let local_player_pawn_offset = read_offset("client.dll", "dwLocalPlayerPawn").unwrap();
+        let offset = read_offset("client.dll", "dwPrediction").unwrap();
 
-        let game_scene_node_offset =
-            read_class_field("client.dll", "C_BaseEntity", "m_pGameSceneNode").unwrap();
+        // The singleton is stored inline, so its first field is the vtable pointer.
+        let vtable: u64 = process.read(client_base + offset).data_part()?;
 
-        let origin_offset =
-            read_class_field("client.dll", "CGameSceneNode", "m_vecAbsOrigin").unwrap();
+        assert_ne!(vtable, 0);
 
-        let local_player_pawn: u64 = process
-            .read(client_base + local_player_pawn_offset)
-            .data_part()?;
+        debug!("[prediction] vtable: {:#X}", vtable);
 
-        let game_scene_node: u64 = process
-            .read((local_player_pawn + game_scene_node_offset).into())
-            .data_part()?;
+        Ok(())
+    }
+
+    #[test]
+    #[ignore = "needs the game running"]
+    fn schema_system() -> Result<()> {
+        let mut process = setup()?;
+
+        let schema_system_base = process.module_by_name("schemasystem.dll")?.base;
+
+        let offset = read_offset("schemasystem.dll", "dwSchemaSystem").unwrap();
+
+        let schema_system: SchemaSystem = process.read(schema_system_base + offset).data_part()?;
+
+        let type_scope_count = schema_system.type_scopes.count;
+
+        debug!("[schema system] type scope count: {}", type_scope_count);
 
-        let origin: Vector3D = process
-            .read((game_scene_node + origin_offset).into())
+        assert!(type_scope_count > 0 && type_scope_count < 0x100);
+
+        Ok(())
+    }
+
+    #[test]
+    #[ignore = "needs the game running"]
+    fn sensitivity() -> Result<()> {
+        let mut process = setup()?;
+
+        let input_system_base = process.module_by_name("inputsystem.dll")?.base;
+
+        let ptr_offset = read_offset("inputsystem.dll", "dwSensitivityPtr").unwrap();
+        let sensitivity_offset = read_offset("inputsystem.dll", "dwSensitivity").unwrap();
+
+        let sensitivity_ptr: u64 = process.read(input_system_base + ptr_offset).data_part()?;
+
+        let sensitivity: f32 = process
+            .read((sensitivity_ptr + sensitivity_offset).into())
             .data_part()?;
 
-        debug!(
-            "[local pawn] origin: {:.2}, y: {:.2}, z: {:.2}",
-            origin.x, origin.y, origin.z
-        );
+        assert!(sensitivity > 0.0);
+
+        debug!("sensitivity: {:.2}", sensitivity);
 
         Ok(())
     }
 
     #[test]
+    #[ignore = "needs the game running"]
     fn window_size() -> Result<()> {
         let mut process = setup()?;
 
@@ -273,6 +1598,52 @@ mod tests {
         Ok(())
     }
 
+    // The counter is read with the Windows API, to compare against the one the game reads.
+    #[cfg(windows)]
+    #[test]
+    #[ignore = "needs the game running"]
+    fn performance_counter() -> Result<()> {
+        unsafe extern "system" {
+            fn QueryPerformanceCounter(count: *mut i64) -> i32;
+        }
+
+        let mut process = setup()?;
+
+        let tier0_base = process.module_by_name("tier0.dll")?.base;
+
+        let start_offset = read_offset("tier0.dll", "dwPerformanceCounterStart").unwrap();
+        let to_seconds_offset = read_offset("tier0.dll", "dwPerformanceCounterToSeconds").unwrap();
+
+        let start: i64 = process.read(tier0_base + start_offset).data_part()?;
+        let to_seconds: f64 = process.read(tier0_base + to_seconds_offset).data_part()?;
+
+        // The performance counter is shared by every process, so this is what `Plat_FloatTime`
+        // returns in the game.
+        let float_time = || {
+            let mut count = 0;
+
+            unsafe { QueryPerformanceCounter(&mut count) };
+
+            (count - start) as f64 * to_seconds
+        };
+
+        let first = float_time();
+
+        std::thread::sleep(std::time::Duration::from_millis(50));
+
+        let second = float_time();
+
+        debug!(
+            "[tier0.dll] Plat_FloatTime: {:.3}s -> {:.3}s (seconds per tick: {:e})",
+            first, second, to_seconds
+        );
+
+        assert!(first > 0.0);
+        assert!(second > first);
+
+        Ok(())
+    }
+
     fn setup() -> Result<IntoProcessInstanceArcBox<'static>> {
         static LOGGER: Once = Once::new();
 
@@ -282,32 +1653,44 @@ mod tests {
 
         let os = memflow_native::create_os(&OsArgs::default(), LibArc::default())?;
 
-        let process = os.into_process_by_name("project8.exe")?;
+        // Renamed builds can be tested by overriding the process name.
+        let process_name =
+            std::env::var("DEADLOCK_PROCESS_NAME").unwrap_or_else(|_| "project8.exe".to_string());
+
+        let process = os.into_process_by_name(&process_name)?;
 
         Ok(process)
     }
 
-    fn read_class_field(module_name: &str, class_name: &str, field_name: &str) -> Option<u64> {
-        let content =
-            fs::read_to_string(format!("output/{}.json", module_name.replace(".", "_"))).ok()?;
+    /// The output directory of the dump the runtime tests check against, which can be overridden
+    /// to run them from another working directory.
+    pub(crate) fn output_dir() -> PathBuf {
+        std::env::var_os("DEADLOCK_OUTPUT_DIR")
+            .map_or_else(|| PathBuf::from("output"), PathBuf::from)
+    }
 
-        let value: Value = serde_json::from_str(&content).ok()?;
+    fn read_offset(module_name: &str, offset_name: &str) -> Option<u64> {
+        let offsets = RvaMap::load(&output_dir().join("offsets.json")).ok()?;
 
-        value
-            .get(module_name)?
-            .get("classes")?
-            .get(class_name)?
-            .get("fields")?
-            .get(field_name)?
-            .as_u64()
+        offsets.get(module_name, offset_name).map(u64::from)
     }
 
-    fn read_offset(module_name: &str, offset_name: &str) -> Option<u64> {
-        let content = fs::read_to_string("output/offsets.json").ok()?;
-        let value: Value = serde_json::from_str(&content).ok()?;
+    /// Writes an image of `module_name` that's all `int3`s to `dir`, as [`dump_modules_to_disk`]
+    /// would.
+    fn cache_module(dir: &Path, module_name: &str) -> Result<()> {
+        let image = build_image(&[0xCC; 0x20]);
+
+        let cached = CachedModule {
+            base: 0x180000000,
+            size: image.len() as umem,
+        };
 
-        let offset = value.get(module_name)?.get(offset_name)?;
+        fs::write(dir.join(format!("{}.bin", module_name)), &image)?;
+        fs::write(
+            dir.join(format!("{}.json", module_name)),
+            serde_json::to_string(&cached)?,
+        )?;
 
-        offset.as_u64()
+        Ok(())
     }
 }
@@ -0,0 +1,36 @@
+/// Receives progress updates while the modules are scanned for offsets, e.g. to drive a progress
+/// bar. Modules and patterns are scanned in parallel, so the calls can come from any thread and in
+/// any order.
+///
+/// Every method does nothing by default, as does the implementation for `()`.
+pub trait Progress: Sync {
+    /// The scan started, and will go through `modules` modules.
+    fn scan_started(&self, modules: usize) {
+        let _ = modules;
+    }
+
+    /// `len` bytes at `offset` into the `size` bytes of `module_name` were read. Modules are read
+    /// in chunks of [`ScanOptions::chunk_size`](super::ScanOptions::chunk_size) bytes, so this is
+    /// called several times for each of them.
+    fn chunk_read(&self, module_name: &str, offset: usize, len: usize, size: usize) {
+        let _ = (module_name, offset, len, size);
+    }
+
+    /// `module_name` was read, and `patterns` patterns will be scanned for in it.
+    fn module_started(&self, module_name: &str, patterns: usize) {
+        let _ = (module_name, patterns);
+    }
+
+    /// The pattern for `name` was scanned for, and matched if `found` is set.
+    fn pattern_resolved(&self, module_name: &str, name: &str, found: bool) {
+        let _ = (module_name, name, found);
+    }
+
+    /// Every pattern of `module_name` was scanned for, of which `found` matched and `missing`
+    /// didn't.
+    fn module_finished(&self, module_name: &str, found: usize, missing: usize) {
+        let _ = (module_name, found, missing);
+    }
+}
+
+impl Progress for () {}
@@ -1,17 +1,31 @@
 use std::collections::BTreeMap;
+#[cfg(feature = "scan")]
+use std::collections::{HashMap, HashSet};
+#[cfg(feature = "scan")]
 use std::ffi::CStr;
 
+#[cfg(feature = "scan")]
 use anyhow::{Result, bail};
 
-use log::debug;
+#[cfg(feature = "scan")]
+use log::{debug, warn};
 
+#[cfg(feature = "scan")]
 use memflow::prelude::v1::*;
 
-use pelite::pattern;
-use pelite::pe64::{Pe, PeView};
+#[cfg(feature = "scan")]
+use pelite::pe64::{PeView, Rva};
 
 use serde::{Deserialize, Serialize};
 
+#[cfg(feature = "scan")]
+use super::offsets::read_module;
+#[cfg(feature = "scan")]
+use super::{OffsetMap, Progress, ScanOptions, scan_view};
+
+#[cfg(feature = "scan")]
+use crate::memory::string::read_name;
+#[cfg(feature = "scan")]
 use crate::source2::*;
 
 pub type SchemaMap = BTreeMap<String, (Vec<Class>, Vec<Enum>)>;
@@ -30,6 +44,8 @@ pub struct Class {
     pub parent_name: Option<String>,
     pub metadata: Vec<ClassMetadata>,
     pub fields: Vec<ClassField>,
+    /// Own and inherited fields, keyed by name.
+    pub netvars: BTreeMap<String, i32>,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -37,12 +53,32 @@ pub struct ClassField {
     pub name: String,
     pub type_name: String,
     pub offset: i32,
+    pub metadata: Vec<FieldMetadata>,
+}
+
+/// A metadata entry of a field, e.g. `MNetworkBitCount`, which describes how its value is
+/// networked.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FieldMetadata {
+    pub name: String,
+    pub value: Option<MetadataValue>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum MetadataValue {
+    Int(i32),
+    Float(f32),
+    String(String),
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Enum {
     pub name: String,
     pub alignment: u8,
+    /// The size of the underlying type, in bytes.
+    pub type_size: u8,
+    /// The number of members.
     pub size: u16,
     pub members: Vec<EnumMember>,
 }
@@ -60,11 +96,26 @@ pub struct TypeScope {
     pub enums: Vec<Enum>,
 }
 
-pub fn schemas<P: Process + MemoryView>(process: &mut P) -> Result<SchemaMap> {
-    let schema_system = read_schema_system(process)?;
+/// Walks every type scope registered with the `SchemaSystem` singleton in `schemasystem.dll`,
+/// collecting the classes (with their field offsets) and enums declared by each module.
+///
+/// Each module is written to `<module>_dll.json` as `classes -> <ClassName> -> fields ->
+/// <FieldName>`.
+///
+/// The singleton is found through the `dwSchemaSystem` in `offsets`. If it isn't there, e.g.
+/// because `schemasystem.dll` was filtered out of the scan, the module is read with `options` and
+/// scanned for it.
+#[cfg(feature = "scan")]
+pub fn schemas<P: Process + MemoryView>(
+    process: &mut P,
+    offsets: &OffsetMap,
+    options: &ScanOptions,
+    progress: &dyn Progress,
+) -> Result<SchemaMap> {
+    let (schema_system, rva) = read_schema_system(process, offsets, options, progress)?;
     let type_scopes = read_type_scopes(process, &schema_system)?;
 
-    Ok(type_scopes
+    let mut map: SchemaMap = type_scopes
         .into_iter()
         .map(|type_scope| {
             (
@@ -72,9 +123,84 @@ pub fn schemas<P: Process + MemoryView>(process: &mut P) -> Result<SchemaMap> {
                 (type_scope.classes, type_scope.enums),
             )
         })
-        .collect())
+        .collect();
+
+    ensure_classes(&map, rva)?;
+
+    resolve_netvars(&mut map);
+
+    Ok(map)
 }
 
+/// Fails if no module declares any classes. Every build declares thousands of them, so finding none
+/// means `dwSchemaSystem` (at `rva`) resolved to something other than the schema system, and
+/// writing the empty result would pass for a successful dump.
+#[cfg(feature = "scan")]
+fn ensure_classes(map: &SchemaMap, rva: Rva) -> Result<()> {
+    if map.values().all(|(classes, _)| classes.is_empty()) {
+        bail!(
+            "no schema classes found, dwSchemaSystem (schemasystem.dll + {:#X}) is likely outdated",
+            rva
+        );
+    }
+
+    Ok(())
+}
+
+/// Flattens the fields of every class together with the fields inherited from its parents.
+#[cfg(feature = "scan")]
+fn resolve_netvars(map: &mut SchemaMap) {
+    let index: HashMap<_, _> = map
+        .values()
+        .flat_map(|(classes, _)| classes)
+        .map(|class| {
+            (
+                class.name.clone(),
+                (class.parent_name.clone(), class.fields.clone()),
+            )
+        })
+        .collect();
+
+    for (classes, _) in map.values_mut() {
+        for class in classes {
+            class.netvars = flatten_fields(&index, &class.name);
+        }
+    }
+}
+
+#[cfg(feature = "scan")]
+fn flatten_fields(
+    index: &HashMap<String, (Option<String>, Vec<ClassField>)>,
+    class_name: &str,
+) -> BTreeMap<String, i32> {
+    let mut netvars = BTreeMap::new();
+    let mut visited = HashSet::new();
+
+    let mut current = Some(class_name);
+
+    while let Some(name) = current {
+        if !visited.insert(name) {
+            warn!("cyclic parent reference in class \"{}\"", class_name);
+
+            break;
+        }
+
+        let Some((parent_name, fields)) = index.get(name) else {
+            break;
+        };
+
+        for field in fields {
+            // Fields declared closer to the derived class take precedence.
+            netvars.entry(field.name.clone()).or_insert(field.offset);
+        }
+
+        current = parent_name.as_deref();
+    }
+
+    netvars
+}
+
+#[cfg(feature = "scan")]
 fn read_class_binding(
     mem: &mut impl MemoryView,
     binding_ptr: Pointer64<SchemaClassBinding>,
@@ -86,9 +212,7 @@ fn read_class_binding(
         .data_part()
         .map(|m| format!("{}.dll", m))?;
 
-    let name = mem
-        .read_utf8_lossy(binding.name.address(), 128)
-        .data_part()?;
+    let name = read_name(mem, binding.name.address(), 128)?;
 
     if name.is_empty() {
         bail!("invalid class name");
@@ -98,10 +222,7 @@ fn read_class_binding(
         let base_class = mem.read_ptr(ptr).data_part().ok()?;
         let parent_class = mem.read_ptr(base_class.class).data_part().ok()?;
 
-        let parent_name = mem
-            .read_utf8_lossy(parent_class.name.address(), 128)
-            .data_part()
-            .ok()?;
+        let parent_name = read_name(mem, parent_class.name.address(), 128).ok()?;
 
         (!parent_name.is_empty()).then_some(parent_name)
     });
@@ -115,9 +236,11 @@ fn read_class_binding(
         parent_name,
         metadata,
         fields,
+        netvars: BTreeMap::new(),
     })
 }
 
+#[cfg(feature = "scan")]
 fn read_class_binding_fields(
     mem: &mut impl MemoryView,
     binding: &SchemaClassBinding,
@@ -133,7 +256,7 @@ fn read_class_binding_fields(
             return Ok(acc);
         }
 
-        let name = mem.read_utf8_lossy(field.name.address(), 128).data_part()?;
+        let name = read_name(mem, field.name.address(), 128)?;
         let r#type = mem.read_ptr(field.r#type).data_part()?;
 
         let type_name = mem
@@ -141,16 +264,71 @@ fn read_class_binding_fields(
             .data_part()?
             .replace(" ", "");
 
+        let metadata = read_field_metadata(mem, &field)?;
+
         acc.push(ClassField {
             name,
             type_name,
             offset: field.offset,
+            metadata,
         });
 
         Ok(acc)
     })
 }
 
+#[cfg(feature = "scan")]
+fn read_field_metadata(
+    mem: &mut impl MemoryView,
+    field: &SchemaClassFieldData,
+) -> Result<Vec<FieldMetadata>> {
+    if field.metadata.is_null() {
+        return Ok(Vec::new());
+    }
+
+    (0..field.metadata_count).try_fold(Vec::new(), |mut acc, i| {
+        let metadata = mem.read_ptr(field.metadata.at(i as _)).data_part()?;
+
+        let name = mem
+            .read_utf8_lossy(metadata.name.address(), 128)
+            .data_part()?;
+
+        // Flags such as `MNetworkEnable` don't have a value.
+        let value = if metadata.network_value.is_null() {
+            None
+        } else {
+            let network_value = mem.read_ptr(metadata.network_value).data_part()?;
+
+            match name.as_str() {
+                "MNetworkAlias"
+                | "MNetworkChangeCallback"
+                | "MNetworkEncoder"
+                | "MNetworkSerializer"
+                | "MNetworkTypeAlias"
+                | "MNetworkUserGroup" => {
+                    let value = mem
+                        .read_utf8_lossy(unsafe { network_value.value.name_ptr }.address(), 128)
+                        .data_part()?;
+
+                    Some(MetadataValue::String(value))
+                }
+                "MNetworkBitCount" | "MNetworkEncodeFlags" | "MNetworkPriority" => {
+                    Some(MetadataValue::Int(unsafe { network_value.value.int_value }))
+                }
+                "MNetworkMaxValue" | "MNetworkMinValue" => Some(MetadataValue::Float(unsafe {
+                    network_value.value.float_value
+                })),
+                _ => None,
+            }
+        };
+
+        acc.push(FieldMetadata { name, value });
+
+        Ok(acc)
+    })
+}
+
+#[cfg(feature = "scan")]
 fn read_class_binding_metadata(
     mem: &mut impl MemoryView,
     binding: &SchemaClassBinding,
@@ -205,15 +383,14 @@ fn read_class_binding_metadata(
     })
 }
 
+#[cfg(feature = "scan")]
 fn read_enum_binding(
     mem: &mut impl MemoryView,
     binding_ptr: Pointer64<SchemaEnumBinding>,
 ) -> Result<Enum> {
     let binding = mem.read_ptr(binding_ptr).data_part()?;
 
-    let name = mem
-        .read_utf8_lossy(binding.name.address(), 128)
-        .data_part()?;
+    let name = read_name(mem, binding.name.address(), 128)?;
 
     if name.is_empty() {
         bail!("invalid enum name");
@@ -224,11 +401,13 @@ fn read_enum_binding(
     Ok(Enum {
         name,
         alignment: binding.alignment,
+        type_size: binding.size,
         size: binding.enumerator_count,
         members,
     })
 }
 
+#[cfg(feature = "scan")]
 fn read_enum_binding_members(
     mem: &mut impl MemoryView,
     binding: &SchemaEnumBinding,
@@ -240,46 +419,76 @@ fn read_enum_binding_members(
     (0..binding.enumerator_count).try_fold(Vec::new(), |mut acc, i| {
         let r#enum = mem.read_ptr(binding.enumerators.at(i as _)).data_part()?;
 
-        let name = mem
-            .read_utf8_lossy(r#enum.name.address(), 128)
-            .data_part()?;
+        let name = read_name(mem, r#enum.name.address(), 128)?;
 
         acc.push(EnumMember {
             name,
-            value: unsafe { r#enum.value.ulong } as i64,
+            value: enumerator_value(
+                unsafe { r#enum.value.ulong },
+                binding.size,
+                binding.min_enumerator_value < 0,
+            ),
         });
 
         Ok(acc)
     })
 }
 
-fn read_schema_system<P: Process + MemoryView>(process: &mut P) -> Result<SchemaSystem> {
-    let module = process.module_by_name("schemasystem.dll")?;
-
-    let buf = process
-        .read_raw(module.base, module.size as _)
-        .data_part()?;
-
-    let view = PeView::from_bytes(&buf)?;
+/// Truncates the raw value of an enumerator to the size of the enum's underlying type, which is
+/// sign-extended if the enum has negative values.
+#[cfg(feature = "scan")]
+fn enumerator_value(raw: u64, size: u8, signed: bool) -> i64 {
+    match (size, signed) {
+        (1, true) => raw as u8 as i8 as i64,
+        (2, true) => raw as u16 as i16 as i64,
+        (4, true) => raw as u32 as i32 as i64,
+        (1, false) => raw as u8 as i64,
+        (2, false) => raw as u16 as i64,
+        (4, false) => raw as u32 as i64,
+        _ => raw as i64,
+    }
+}
 
-    let mut save = [0; 2];
+#[cfg(feature = "scan")]
+fn read_schema_system<P: Process + MemoryView>(
+    process: &mut P,
+    offsets: &OffsetMap,
+    options: &ScanOptions,
+    progress: &dyn Progress,
+) -> Result<(SchemaSystem, Rva)> {
+    let scanned = offsets
+        .get("schemasystem.dll")
+        .and_then(|offsets| offsets.get("dwSchemaSystem"));
+
+    let (module, rva) = match scanned {
+        Some(rva) => (process.module_by_name("schemasystem.dll")?, Some(*rva)),
+        None => {
+            let (module, buf) = read_module(process, "schemasystem.dll", options, progress)?;
+
+            let rva = scan_view(PeView::from_bytes(&buf)?, "schemasystem.dll")
+                .and_then(|offsets| offsets.get("dwSchemaSystem").copied());
+
+            (module, rva)
+        }
+    };
 
-    if !view
-        .scanner()
-        .finds_code(pattern!("4c8d35${'} 0f2845"), &mut save)
-    {
+    let Some(rva) = rva else {
         bail!("outdated schema system pattern");
-    }
+    };
 
-    let schema_system: SchemaSystem = process.read(module.base + save[1]).data_part()?;
+    let schema_system: SchemaSystem = process.read(module.base + rva).data_part()?;
 
     if schema_system.registration_count == 0 {
-        bail!("no schema registrations");
+        bail!(
+            "no schema registrations, dwSchemaSystem (schemasystem.dll + {:#X}) is likely outdated",
+            rva
+        );
     }
 
-    Ok(schema_system)
+    Ok((schema_system, rva))
 }
 
+#[cfg(feature = "scan")]
 fn read_type_scopes(
     mem: &mut impl MemoryView,
     schema_system: &SchemaSystem,
@@ -328,3 +537,136 @@ fn read_type_scopes(
         Ok(acc)
     })
 }
+
+#[cfg(all(test, feature = "scan"))]
+mod tests {
+    use super::*;
+
+    use crate::analysis::fixtures::class;
+    use crate::analysis::offsets::tests::output_dir;
+
+    fn netvars<'a>(map: &'a SchemaMap, class_name: &str) -> &'a BTreeMap<String, i32> {
+        &map["client.dll"]
+            .0
+            .iter()
+            .find(|class| class.name == class_name)
+            .unwrap()
+            .netvars
+    }
+
+    #[test]
+    fn empty_schemas() {
+        let mut map = SchemaMap::from([("client.dll".to_string(), (Vec::new(), Vec::new()))]);
+
+        let err = ensure_classes(&map, 0x5F3A0).unwrap_err();
+
+        assert!(err.to_string().contains("0x5F3A0"));
+        assert!(ensure_classes(&SchemaMap::new(), 0x5F3A0).is_err());
+
+        map.get_mut("client.dll")
+            .unwrap()
+            .0
+            .push(class("C_BaseEntity", None, &[]));
+
+        assert!(ensure_classes(&map, 0x5F3A0).is_ok());
+    }
+
+    #[test]
+    fn field_metadata() -> Result<()> {
+        let metadata = [
+            FieldMetadata {
+                name: "MNetworkEnable".to_string(),
+                value: None,
+            },
+            FieldMetadata {
+                name: "MNetworkBitCount".to_string(),
+                value: Some(MetadataValue::Int(10)),
+            },
+            FieldMetadata {
+                name: "MNetworkEncoder".to_string(),
+                value: Some(MetadataValue::String("coord".to_string())),
+            },
+        ];
+
+        assert_eq!(
+            serde_json::to_string(&metadata)?,
+            r#"[{"name":"MNetworkEnable","value":null},{"name":"MNetworkBitCount","value":10},{"name":"MNetworkEncoder","value":"coord"}]"#
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn enum_values() {
+        assert_eq!(enumerator_value(0xFFFF_FFFF_FFFF_FFFF, 4, true), -1);
+        assert_eq!(enumerator_value(0x0000_0000_FFFF_FFFF, 4, true), -1);
+        assert_eq!(
+            enumerator_value(0x0000_0000_FFFF_FFFF, 4, false),
+            0xFFFF_FFFF
+        );
+        assert_eq!(enumerator_value(0xFFFF_FFFF_FFFF_FF80, 1, true), -128);
+        assert_eq!(enumerator_value(0x80, 1, false), 0x80);
+        assert_eq!(enumerator_value(0x8000_0000_0000_0000, 8, false), i64::MIN);
+    }
+
+    #[test]
+    #[ignore = "needs the game running"]
+    fn enums() -> Result<()> {
+        let content = std::fs::read_to_string(output_dir().join("enums.json"))?;
+        let value: serde_json::Value = serde_json::from_str(&content)?;
+
+        let life_state = value
+            .get("client.dll")
+            .and_then(|module| module.get("LifeState_t"))
+            .unwrap();
+
+        assert_eq!(life_state["size"], 4);
+        assert_eq!(
+            life_state["members"].as_object().map(|map| map.len()),
+            Some(5)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn inherited_netvars() {
+        let classes = vec![
+            class("C_BaseEntity", None, &[("m_pGameSceneNode", 0x330)]),
+            class(
+                "C_BaseModelEntity",
+                Some("C_BaseEntity"),
+                &[("m_Glow", 0xC00)],
+            ),
+            class(
+                "C_CitadelPlayerPawn",
+                Some("C_BaseModelEntity"),
+                &[("m_iLevel", 0x1200)],
+            ),
+        ];
+
+        let mut map = SchemaMap::from([("client.dll".to_string(), (classes, Vec::new()))]);
+
+        resolve_netvars(&mut map);
+
+        let base = netvars(&map, "C_BaseEntity");
+        let derived = netvars(&map, "C_CitadelPlayerPawn");
+
+        assert_eq!(derived.len(), 3);
+        assert_eq!(derived["m_pGameSceneNode"], base["m_pGameSceneNode"]);
+    }
+
+    #[test]
+    fn cyclic_netvars() {
+        let classes = vec![
+            class("A", Some("B"), &[("m_a", 0x10)]),
+            class("B", Some("A"), &[("m_b", 0x20)]),
+        ];
+
+        let mut map = SchemaMap::from([("client.dll".to_string(), (classes, Vec::new()))]);
+
+        resolve_netvars(&mut map);
+
+        assert_eq!(netvars(&map, "A").len(), 2);
+    }
+}
@@ -0,0 +1,2090 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::RangeInclusive;
+use std::time::Duration;
+
+use log::{debug, error, warn};
+
+use pelite::image::{IMAGE_SCN_MEM_EXECUTE, IMAGE_SECTION_HEADER};
+use pelite::pattern;
+use pelite::pattern::{Atom, save_len};
+use pelite::pe64::{Pe, PeView, Rva};
+
+use phf::{Map, phf_map};
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer};
+
+/// The result of scanning a single module.
+#[derive(Debug, Default)]
+pub struct ModuleScan {
+    pub offsets: BTreeMap<String, Rva>,
+    pub missing: BTreeSet<String>,
+    pub match_counts: BTreeMap<String, usize>,
+    /// Names of the offsets that were only found by their recovery pattern.
+    pub recovered: BTreeSet<String>,
+    /// The sections each offset was found in.
+    pub sections: BTreeMap<String, MatchSections>,
+    /// The bytes each function starts with, as a hex string, if [`ScanOptions::prologue_len`] is
+    /// set.
+    pub prologues: BTreeMap<String, String>,
+}
+
+/// The names of the PE sections a match falls into, or `None` if it isn't within any of them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MatchSections {
+    /// The section containing the instruction the pattern matched.
+    pub site: Option<String>,
+    /// The section the captured value points into, for offsets that are addresses.
+    pub value: Option<String>,
+}
+
+/// Where a module was loaded, as written to `offsets.json` under the reserved `__module__` key.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+pub struct ModuleLocation {
+    /// `None` if the module wasn't read from the game or a cached image of it.
+    pub base: Option<u64>,
+    pub size: Option<u64>,
+    pub image_base: u64,
+}
+
+/// Declares the patterns scanned for in each module.
+///
+/// Each name maps to one or more patterns followed by an optional callback. Patterns listed after
+/// the first are fallbacks, tried in order until one matches:
+///
+/// ```ignore
+/// "dwFoo" as dw_foo => pattern!("488b05${'} 4885c0"), pattern!("488b0d${'} 4885c9") => None,
+/// ```
+///
+/// The identifier after `as` names the field of the offset in the module's typed [`Offsets`]
+/// struct.
+///
+/// RIP-relative operands (e.g. `mov rax, [rip + disp]`) are captured with `${'}`, which follows
+/// the displacement and saves the RVA it points to. Register-relative operands (e.g. `lea rax,
+/// [r15 + disp]`) don't point into the module, so their displacement is read as is with `u4` (or
+/// `u1`/`u2`) instead; such captures are struct offsets and are rejected unless they're below
+/// [`MAX_DISPLACEMENT`]. `u1`, `u2` and `u4` zero-extend the displacement, whereas `i1`, `i2` and
+/// `i4` sign-extend it, so a negative displacement read that way is always rejected.
+/// [`check_displacement`] can be used as the callback to warn about displacements above a tighter,
+/// pattern specific bound.
+///
+/// Every pattern has to capture exactly one value, which is checked at compile time. Patterns that
+/// capture more than one have to name the capture they're read from, counting from 1:
+///
+/// ```ignore
+/// #[capture = 2] "dwFoo" as dw_foo => pattern!("8b05${'} 8b81u4") => None,
+/// ```
+///
+/// A looser recovery pattern can be given as well, which is only tried if none of the others match.
+/// Offsets found that way are logged, so the primary pattern can be fixed:
+///
+/// ```ignore
+/// #[recovery = pattern!("488b05${'}")] "dwFoo" as dw_foo => pattern!("488b05${'} 4885c0") => None,
+/// ```
+///
+/// An offset that was renamed keeps its previous names as aliases, which are written alongside it
+/// with the same value so consumers have time to switch over:
+///
+/// ```ignore
+/// #[alias = "dwOldFoo"] "dwFoo" as dw_foo => pattern!("488b05${'} 4885c0") => None,
+/// ```
+///
+/// Aliases are only kept for a deprecation window. They're removed together with the next bump of
+/// [`OUTPUT_SCHEMA_VERSION`](crate::output::OUTPUT_SCHEMA_VERSION), and only once they've been
+/// written by at least one release.
+///
+/// Every block names the DLL it's scanned in with a `#[dll = "..."]` attribute, and is registered
+/// in [`MODULES`] automatically.
+macro_rules! pattern_map {
+    ($(#[dll = $dll:literal] $module:ident => {
+        $($(#[capture = $capture:literal])? $(#[recovery = $recovery:expr])? $(#[alias = $alias:literal])* $name:literal as $field:ident => $pattern:expr $(, $fallback:expr)* => $callback:expr),+ $(,)?
+    }),+ $(,)?) => {
+        $(
+            pub mod $module {
+                use super::*;
+
+                #[doc = concat!("The offsets found in `", $dll, "`, as read back from `offsets.json`.")]
+                /// Offsets whose pattern didn't match are `None`.
+                #[derive(Clone, Debug, Default, Deserialize)]
+                pub struct Offsets {
+                    $(
+                        #[serde(rename = $name, default)]
+                        pub $field: Option<Rva>,
+                    )+
+                    #[serde(rename = "__module__", default)]
+                    pub module: Option<ModuleLocation>,
+                    /// Offsets that aren't scanned for directly, e.g. the ones added by callbacks.
+                    /// The prologues written next to the offsets are skipped.
+                    #[serde(flatten, deserialize_with = "other_offsets")]
+                    pub other: BTreeMap<String, Rva>,
+                }
+
+                pub(super) const PATTERNS: PatternTable = phf_map! {
+                    $($name => (
+                        &[$pattern $(, $fallback)*],
+                        capture_slot!($($capture)?).0,
+                        $callback,
+                        recovery!($($recovery)?),
+                    )),+
+                };
+
+                $(
+                    const _: () = assert!(
+                        valid_captures(
+                            &[$pattern $(, $fallback)* $(, $recovery)?],
+                            capture_slot!($($capture)?).0,
+                            capture_slot!($($capture)?).1,
+                        ),
+                        concat!(
+                            "every pattern of ",
+                            $name,
+                            " must capture exactly one value, or name the one it uses with #[capture = N]"
+                        ),
+                    );
+                )+
+
+                pub(super) const SOURCES: PatternSources = phf_map! {
+                    $($name => &[stringify!($pattern) $(, stringify!($fallback))*]),+
+                };
+
+                pub(super) const ALIASES: AliasTable = phf_map! {
+                    $($name => &[$($alias),*]),+
+                };
+
+                pub fn offsets(view: PeView<'_>, options: &ScanOptions) -> ModuleScan {
+                    scan_module(&view, $dll, &PATTERNS, &ALIASES, options)
+                }
+            }
+        )+
+
+        pub(super) static MODULES: &[ModuleEntry] = &[
+            $(($dll, &$module::PATTERNS, &$module::SOURCES, &$module::ALIASES)),+
+        ];
+
+        /// The offsets of every module, with a field for each registered pattern. Modules that
+        /// weren't scanned have none of their offsets.
+        #[derive(Clone, Debug, Default, Deserialize)]
+        pub struct Offsets {
+            $(
+                #[serde(rename = $dll, default)]
+                pub $module: $module::Offsets,
+            )+
+        }
+    };
+}
+
+/// A value written in a module of `offsets.json` besides its registered offsets, which is either
+/// another offset or the prologue of a function.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ModuleValue {
+    Offset(Rva),
+    Prologue(String),
+}
+
+/// Reads the offsets of a module that don't have a field in its typed [`Offsets`], skipping the
+/// prologues.
+fn other_offsets<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<BTreeMap<String, Rva>, D::Error> {
+    let mut offsets = BTreeMap::new();
+
+    for (name, value) in BTreeMap::<String, ModuleValue>::deserialize(deserializer)? {
+        match value {
+            ModuleValue::Offset(rva) => {
+                offsets.insert(name, rva);
+            }
+            ModuleValue::Prologue(_) if name.ends_with(PROLOGUE_SUFFIX) => {}
+            ModuleValue::Prologue(value) => {
+                return Err(D::Error::custom(format!(
+                    "{} isn't an RVA: \"{}\"",
+                    name, value
+                )));
+            }
+        }
+    }
+
+    Ok(offsets)
+}
+
+/// The recovery pattern of an offset, if it has one.
+macro_rules! recovery {
+    () => {
+        None
+    };
+    ($recovery:expr) => {
+        Some($recovery)
+    };
+}
+
+/// The capture slot an offset is read from, and whether it was named explicitly with
+/// `#[capture = N]`.
+macro_rules! capture_slot {
+    () => {
+        (1, false)
+    };
+    ($capture:literal) => {
+        ($capture, true)
+    };
+}
+
+pattern_map! {
+    // Deadlock patterns (client.dll) - Updated from UC thread page 55
+    #[dll = "client.dll"]
+    client => {
+        // The player controllers are the entities at indices 1 to 64, which are found through the
+        // chunk and entry sizes derived alongside it (see `EntityReader::player_controllers`).
+        //
+        // 48 89 35 ?? ?? ?? ?? 48 85 F6
+        "dwEntityList" as dw_entity_list => pattern!("488935${'} 4885f6") => Some(entity_list_layout),
+        // 48 8B 35 ?? ?? ?? ?? 4C 89 B4 24 ?? ?? ?? ?? 4C 89 BC 24
+        "dwGameEntitySystem" as dw_game_entity_system => pattern!("488b35${'} 4c89b424???? 4c89bc24") => Some(entity_system_bounds),
+        // 48 3B 35
+        // The local pawn is loaded right after the controller is compared against, so it's
+        // followed from there rather than scanned for separately.
+        "dwLocalPlayerController" as dw_local_player_controller => pattern!("483b35${'}") => Some(controller_local_pawn),
+        // 49 8D 87 ?? ?? ?? ?? 4D 69 F4
+        // Note: This is register-relative (LEA r8, [r15+disp]), not RIP-relative
+        // So we capture the displacement directly with u4 instead of ${'}
+        "dwViewMatrix" as dw_view_matrix => pattern!("498d87 u4 4d69f4") => Some(check_displacement::<0x10000>),
+        // The camera manager is a global object rather than a pointer to one, so the view angles
+        // it holds are stored as a global of their own, `dwViewAngles`.
+        //
+        // 48 8D 3D ?? ?? ?? ?? 8B D9
+        "dwCCitadelCameraManager" as dw_c_citadel_camera_manager => pattern!("488d3d${'} 8bd9") => Some(camera_view_angles),
+        // 48 89 05 ?? ?? ?? ?? 48 8B C8 48 85 C0
+        "dwViewRender" as dw_view_render => pattern!("488905${'} 488bc8 4885c0") => None,
+        // 48 89 15 ?? ?? ?? ?? 48 89 42
+        "dwGlobalVars" as dw_global_vars => pattern!("488915${'} 488942") => None,
+        // 48 8B 0D ?? ?? ?? ?? 4C 8D 44 24 ?? E8 ?? ?? ?? ?? E8
+        "dwGameTraceManager" as dw_game_trace_manager => pattern!("488b0d${'} 4c8d4424? e8???? e8") => None,
+        // Functions are captured by following the `call` to them, so their offsets are the RVA of
+        // the callee (see `resolve_call_target`), not the displacement.
+        //
+        // E8 ?? ?? ?? ?? 48 85 C0 74 ?? 48 8B 40 ?? 48 8D 0D
+        "fnGetCmd" as fn_get_cmd => pattern!("e8${'} 4885c0 74? 488b40? 488d0d") => None,
+        // E8 ?? ?? ?? ?? 48 8B 53 ?? 48 3B D5
+        "fnDecodeNetworkEntities" as fn_decode_network_entities => pattern!("e8${'} 488b53? 483bd5") => None,
+        // 4C 8D 35 ?? ?? ?? ?? 0F 28 45
+        "dwSchemas" as dw_schemas => pattern!("4c8d35${'} 0f2845") => None,
+        // 48 8D 05 ?? ?? ?? ?? C3 CC CC CC CC CC CC CC CC 48 8D 05 ?? ?? ?? ??
+        "dwMaterialSystem" as dw_material_system => pattern!("488d05${'} c3 cccccccccccccccc 488d05????") => None,
+        // 48 8D 05 ?? ?? ?? ?? C3 CC CC CC CC CC CC CC CC 48 83 EC ?? 8B 0D
+        "dwPrediction" as dw_prediction => pattern!("488d05${'} c3 cccccccccccccccc 4883ec? 8b0d") => None,
+
+        // Game mode singletons. These are only created once a match has loaded, so the pointers
+        // they hold are null in the main menu and while loading into a match.
+        //
+        // 48 8B 0D ?? ?? ?? ?? 48 85 C9 74 ?? 8B 81
+        // Holds the `C_CitadelGameRules`, which is also reachable through the `m_pGameRules` field
+        // of the `C_CitadelGameRulesProxy` entity.
+        "dwGameRules" as dw_game_rules => pattern!("488b0d${'} 4885c9 74? 8b81") => None,
+        // 48 8B 05 ?? ?? ?? ?? 48 85 C0 74 ?? 48 8B 80
+        "dwObjectiveManager" as dw_objective_manager => pattern!("488b05${'} 4885c0 74? 488b80") => None,
+        // E8 ?? ?? ?? ?? 48 85 C0 74 ?? 8B 88
+        // Only reachable through its accessor, which is followed to the global it returns.
+        "dwMidbossManager" as dw_midboss_manager => pattern!("e8${'} 4885c0 74? 8b88") => Some(follow_accessor),
+        // 48 89 3D ?? ?? ?? ?? 48 83 C4 ?? 5F C3
+        "dwTubeManager" as dw_tube_manager => pattern!("48893d${'} 4883c4? 5f c3") => None,
+    },
+    #[dll = "engine2.dll"]
+    engine2 => {
+        "dwBuildNumber" as dw_build_number => pattern!("8905${'} 488d0d${} ff15${} 488b0d") => None,
+        "dwNetworkGameClient" as dw_network_game_client => pattern!("48893d${'} ff87") => None,
+        "dwNetworkGameClient_clientTickCount" as dw_network_game_client_client_tick_count => pattern!("8b81u4 c3 cccccccccccccccccc 8b81${} c3 cccccccccccccccccc 83b9") => None,
+        "dwNetworkGameClient_deltaTick" as dw_network_game_client_delta_tick => pattern!("4c8db7u4 4c897c24") => None,
+        "dwNetworkGameClient_isBackgroundMap" as dw_network_game_client_is_background_map => pattern!("0fb681u4 c3 cccccccccccccccc 0fb681${} c3 cccccccccccccccc 4053") => None,
+        "dwNetworkGameClient_localPlayer" as dw_network_game_client_local_player => pattern!("428b94d3u4 5b 49ffe3 32c0 5b c3 cccccccccccccccc 4053") => None,
+        "dwNetworkGameClient_maxClients" as dw_network_game_client_max_clients => pattern!("8b81u4 c3????????? 8b81[4] c3????????? 8b81") => None,
+        "dwNetworkGameClient_serverTickCount" as dw_network_game_client_server_tick_count => pattern!("8b81u4 c3 cccccccccccccccccc 83b9") => None,
+        "dwNetworkGameClient_signOnState" as dw_network_game_client_sign_on_state => pattern!("448b81u4 488d0d") => None,
+        "dwWindowHeight" as dw_window_height => pattern!("8b05${'} 8903") => None,
+        "dwWindowWidth" as dw_window_width => pattern!("8b05${'} 8907") => None,
+    },
+    #[dll = "schemasystem.dll"]
+    schema_system => {
+        // 4C 8D 35 ?? ?? ?? ?? 0F 28 45
+        "dwSchemaSystem" as dw_schema_system => pattern!("4c8d35${'} 0f2845") => None,
+    },
+    #[dll = "inputsystem.dll"]
+    input_system => {
+        #[recovery = pattern!("488d05${'} c3")]
+        "dwInputSystem" as dw_input_system => pattern!("488d05${'} c3 cccccccccccccccc 4053") => None,
+        // 48 8B 05 ?? ?? ?? ?? F3 0F 10 40 ??
+        // The sensitivity float is read through a pointer, so both the global holding the pointer
+        // and the field displacement within the pointed-to object are captured.
+        "dwSensitivityPtr" as dw_sensitivity_ptr => pattern!("488b05${'} f30f1040") => None,
+        "dwSensitivity" as dw_sensitivity => pattern!("488b05${} f30f1040u1") => None,
+    },
+    // The high-precision timer behind `Plat_FloatTime`, which is `(QueryPerformanceCounter() -
+    // start) * seconds per tick`. tier0 rarely changes, so these tend to outlive game updates.
+    #[dll = "tier0.dll"]
+    tier0 => {
+        // 48 2B 05 ?? ?? ?? ?? F2 48 0F 2A C0 F2 0F 59 05 ?? ?? ?? ??
+        "dwPerformanceCounterStart" as dw_performance_counter_start => pattern!("482b05${'} f2480f2ac0 f20f5905${}") => None,
+        "dwPerformanceCounterToSeconds" as dw_performance_counter_to_seconds => pattern!("482b05${} f2480f2ac0 f20f5905${'}") => None,
+    },
+}
+
+/// Upper bound for captured displacements, which are struct offsets rather than addresses.
+const MAX_DISPLACEMENT: Rva = 0x100000;
+
+/// Run once an offset is found, with the map of the offsets found so far in the module, the value
+/// of the offset and the RVA its pattern matched at.
+pub(super) type Callback = fn(&PeView, &mut BTreeMap<String, Rva>, &str, Rva, Rva);
+
+/// The patterns of an offset, the capture slot its value is read from, its callback and its
+/// recovery pattern.
+pub(super) type PatternEntry = (
+    &'static [&'static [Atom]],
+    usize,
+    Option<Callback>,
+    Option<&'static [Atom]>,
+);
+
+pub(super) type PatternTable = Map<&'static str, PatternEntry>;
+
+/// A [`PatternEntry`] selected for a scan. Its patterns can be borrowed from the ones loaded into
+/// [`ScanOptions::patterns`].
+pub(super) type SelectedEntry<'a> = (Vec<&'a [Atom]>, usize, Option<Callback>, Option<&'a [Atom]>);
+
+/// The source text of the patterns registered for each offset name, in the order they're tried.
+pub(super) type PatternSources = Map<&'static str, &'static [&'static str]>;
+
+/// The previous names of each offset, which are written with the same value.
+pub(super) type AliasTable = Map<&'static str, &'static [&'static str]>;
+
+pub(super) type ModuleEntry = (
+    &'static str,
+    &'static PatternTable,
+    &'static PatternSources,
+    &'static AliasTable,
+);
+
+/// Patterns loaded at runtime with [`load_patterns`](super::load_patterns), keyed by module and
+/// offset name.
+pub type ExternalPatterns = BTreeMap<String, BTreeMap<String, Vec<Atom>>>;
+
+/// Restricts which modules and offsets get scanned. Empty lists match everything.
+#[derive(Clone, Debug, Default)]
+pub struct OffsetFilter {
+    pub modules: Vec<String>,
+    /// Globs matched against offset names, where `*` matches any sequence of characters.
+    pub names: Vec<String>,
+}
+
+/// How a module read is retried when it fails or comes back short, e.g. over a flaky connector.
+#[derive(Clone, Copy, Debug)]
+pub struct ReadRetry {
+    pub attempts: u32,
+    /// The delay before the first retry, doubled after every failed attempt.
+    pub base_delay: Duration,
+}
+
+impl Default for ReadRetry {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            base_delay: Duration::from_millis(100),
+        }
+    }
+}
+
+/// The most bytes read from a module at once, unless [`ScanOptions::chunk_size`] is set.
+pub const DEFAULT_CHUNK_SIZE: usize = 0x400000;
+
+#[derive(Clone, Debug, Default)]
+pub struct ScanOptions {
+    pub filter: OffsetFilter,
+    /// Fail if a module can't be found or read, instead of skipping it. The dumper also fails
+    /// after writing its output if any pattern didn't match.
+    pub strict: bool,
+    pub retry: ReadRetry,
+    /// Count every match of each pattern and warn about ambiguous ones, at the cost of scanning
+    /// the whole module for each of them.
+    pub count_matches: bool,
+    /// Look for the likely location of patterns that fail to match and log the bytes around it.
+    pub diagnose_stale: bool,
+    /// Also store the RVA of the instruction each pattern matched, as `<name>_site`.
+    pub record_sites: bool,
+    /// Scanned for in addition to the registered patterns, replacing those with the same name.
+    pub patterns: ExternalPatterns,
+    /// Only read the headers and executable sections of each module, which is a lot less to
+    /// transfer over slow connectors. The rest of the image is left zeroed, which the callbacks
+    /// don't mind, as they only read code.
+    pub code_only: bool,
+    /// The most bytes read from a module at once, as some DMA connectors cap the size of a single
+    /// read. Defaults to [`DEFAULT_CHUNK_SIZE`].
+    pub chunk_size: Option<usize>,
+    /// Keep the image each module was scanned in, e.g. to cache it with
+    /// [`dump_modules_to_disk`](super::dump_modules_to_disk).
+    pub keep_images: bool,
+    /// Also store the first `prologue_len` bytes of the code each function (`fn*`) offset points
+    /// to, e.g. to build a trampoline from. Nothing is stored if this is zero.
+    pub prologue_len: usize,
+}
+
+/// Appended to the name of an offset to name the RVA of the instruction it was found at.
+pub const SITE_SUFFIX: &str = "_site";
+
+/// Appended to the name of a function offset to name the bytes it starts with.
+pub const PROLOGUE_SUFFIX: &str = "_prologue";
+
+/// A successful match of one of the patterns registered for an offset.
+#[derive(Clone, Copy, Debug)]
+pub(super) struct PatternMatch {
+    /// The captured value.
+    pub value: Rva,
+    /// The RVA the match starts at.
+    pub site: Rva,
+    /// How often the pattern matches, if [`ScanOptions::count_matches`] is set.
+    pub count: Option<usize>,
+    /// Whether only the recovery pattern matched.
+    pub recovered: bool,
+}
+
+impl OffsetFilter {
+    pub fn includes_module(&self, module_name: &str) -> bool {
+        self.modules.is_empty()
+            || self
+                .modules
+                .iter()
+                .any(|name| name.eq_ignore_ascii_case(module_name))
+    }
+
+    pub fn includes_name(&self, name: &str) -> bool {
+        self.names.is_empty() || self.names.iter().any(|glob| glob_match(glob, name))
+    }
+}
+
+/// Resolves the target of a RIP-relative operand, e.g. `lea rax, [rip + disp]`, whose displacement
+/// is stored `disp_offset` bytes into an instruction of `instr_len` bytes.
+pub(crate) fn resolve_rip(
+    view: &PeView<'_>,
+    instr_rva: Rva,
+    disp_offset: u8,
+    instr_len: u8,
+) -> Option<Rva> {
+    let disp: i32 = view
+        .derva_copy(instr_rva.checked_add(disp_offset as Rva)?)
+        .ok()?;
+
+    instr_rva
+        .checked_add(instr_len as Rva)?
+        .checked_add_signed(disp)
+}
+
+/// Resolves the callee of a relative `call` (`e8 <disp32>`) at `call_site`, whose displacement is
+/// relative to the end of the 5-byte instruction. This is what `$` does when following a `call`
+/// in a pattern like `e8${'}`, so function offsets never need the call site added to them.
+///
+/// Returns `None` if the callee would lie outside of the image.
+pub(crate) fn resolve_call_target(view: &PeView<'_>, call_site: Rva, disp: i32) -> Option<Rva> {
+    call_site
+        .checked_add(5)?
+        .checked_add_signed(disp)
+        .filter(|target| *target < view.optional_header().SizeOfImage)
+}
+
+/// Tries each of the patterns registered for `name` in order, returning the value captured in
+/// `slot` by the first one that matches. The recovery pattern is only tried if none of them do.
+pub(super) fn scan_patterns(
+    view: &PeView<'_>,
+    name: &str,
+    patterns: &[&[Atom]],
+    slot: usize,
+    recovery: Option<&[Atom]>,
+    options: &ScanOptions,
+) -> Option<PatternMatch> {
+    let find = |pat: &[Atom]| {
+        let mut save = vec![0; save_len(pat)];
+
+        view.scanner()
+            .finds_code(pat, &mut save)
+            .then(|| (save[slot], save[0]))
+    };
+
+    let found = patterns
+        .iter()
+        .enumerate()
+        .find_map(|(index, pat)| find(pat).map(|(rva, site)| (index, *pat, rva, site)));
+
+    let (pat, rva, site, recovered) = match found {
+        Some((index, pat, rva, site)) => {
+            if index > 0 {
+                warn!("\"{}\" matched using fallback pattern #{}", name, index);
+            }
+
+            (pat, rva, site, false)
+        }
+        None => {
+            let recovered = recovery.and_then(|pat| find(pat).map(|(rva, site)| (pat, rva, site)));
+
+            if options.diagnose_stale {
+                diagnose_stale(view, name, patterns);
+            }
+
+            let Some((pat, rva, site)) = recovered else {
+                error!("outdated pattern: {}", name);
+
+                return None;
+            };
+
+            warn!(
+                "\"{}\" only matched its recovery pattern, the primary pattern needs to be updated",
+                name
+            );
+
+            (pat, rva, site, true)
+        }
+    };
+
+    if !is_valid_capture(view, pat, slot, rva) {
+        error!("captured value out of range: {} ({:#X})", name, rva);
+
+        return None;
+    }
+
+    let count = options.count_matches.then(|| {
+        let mut save = vec![0; save_len(pat)];
+        let mut matches = view.scanner().matches_code(pat);
+        let mut count = 0;
+
+        while matches.next(&mut save) {
+            count += 1;
+        }
+
+        if count > 1 {
+            warn!("ambiguous pattern: \"{}\" matched {} times", name, count);
+        }
+
+        count
+    });
+
+    Some(PatternMatch {
+        value: rva,
+        site,
+        count,
+        recovered,
+    })
+}
+
+/// Scans for the leading opcode bytes of each pattern on their own, and logs the bytes around the
+/// first match so it's easier to see which part of the instruction sequence changed.
+fn diagnose_stale(view: &PeView<'_>, name: &str, patterns: &[&[Atom]]) {
+    /// The number of bytes logged before and after the likely match.
+    const CONTEXT: Rva = 32;
+
+    for pat in patterns {
+        // Every pattern starts by saving the cursor, followed by the bytes matched literally.
+        let prefix: Vec<_> = pat
+            .iter()
+            .copied()
+            .enumerate()
+            .take_while(|(i, atom)| *i == 0 || matches!(atom, Atom::Byte(_)))
+            .map(|(_, atom)| atom)
+            .collect();
+
+        // A single opcode byte matches pretty much anywhere.
+        if prefix.len() < 3 {
+            continue;
+        }
+
+        let mut save = vec![0; save_len(&prefix)];
+
+        if !view.scanner().finds_code(&prefix, &mut save) {
+            continue;
+        }
+
+        let rva = save[0];
+        let start = rva.saturating_sub(CONTEXT);
+
+        let Ok(bytes) = view.derva_slice::<u8>(start, (rva - start + CONTEXT) as usize) else {
+            continue;
+        };
+
+        let (before, after) = bytes.split_at((rva - start) as usize);
+
+        warn!(
+            "\"{}\" likely used to match at {:#X} ({} opcode bytes): {} | {}",
+            name,
+            rva,
+            prefix.len() - 1,
+            hex_bytes(before),
+            hex_bytes(after)
+        );
+
+        return;
+    }
+
+    warn!("no likely match for \"{}\"", name);
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Replaces the RVA of an accessor that returns a global (`mov rax, [rip + disp]; ret`) with the
+/// RVA of the global itself.
+fn follow_accessor(
+    view: &PeView,
+    map: &mut BTreeMap<String, Rva>,
+    name: &str,
+    rva: Rva,
+    _site: Rva,
+) {
+    let target = view
+        .derva_slice::<u8>(rva, 8)
+        .ok()
+        .filter(|code| code[..3] == [0x48, 0x8B, 0x05] && code[7] == 0xC3)
+        .and_then(|_| resolve_rip(view, rva, 3, 7));
+
+    match target {
+        Some(target) => {
+            map.insert(name.to_string(), target);
+        }
+        None => {
+            warn!(
+                "\"{}\" doesn't point to an accessor returning a global",
+                name
+            );
+
+            map.remove(name);
+        }
+    }
+}
+
+/// Warns if a register-relative displacement isn't below `MAX`, which usually means the pattern
+/// matched an unrelated instruction.
+fn check_displacement<const MAX: Rva>(
+    _view: &PeView,
+    _map: &mut BTreeMap<String, Rva>,
+    name: &str,
+    disp: Rva,
+    _site: Rva,
+) {
+    if disp >= MAX {
+        warn!(
+            "displacement of \"{}\" is suspiciously large ({:#X} >= {:#X})",
+            name, disp, MAX
+        );
+    }
+}
+
+/// How far past the match of an offset its callback looks for the code it reads from.
+const NEAR_MATCH_WINDOW: Rva = 0x100;
+
+/// Searches the code within [`NEAR_MATCH_WINDOW`] bytes after `site` for `pat`, returning its
+/// captures. Instructions like `cmp edx, imm32` appear all over a module, so looking only next to
+/// the match keeps them from matching unrelated code.
+fn find_near_match(view: &PeView, pat: &[Atom], site: Rva) -> Option<Vec<Rva>> {
+    let end = site
+        .saturating_add(NEAR_MATCH_WINDOW)
+        .min(view.optional_header().SizeOfImage);
+
+    let mut save = vec![0; save_len(pat)];
+
+    view.scanner()
+        .finds(pat, site..end, &mut save)
+        .then_some(save)
+}
+
+/// Derives the layout of the entity list from the code indexing into it right after the match
+/// (`and edx, mask; imul rax, rdx, size`), and stores the number of entries per chunk and the size
+/// of each entry alongside it as `<name>_chunkSize` and `<name>_entrySize`.
+fn entity_list_layout(
+    view: &PeView,
+    map: &mut BTreeMap<String, Rva>,
+    name: &str,
+    _rva: Rva,
+    site: Rva,
+) {
+    let Some(save) = find_near_match(view, pattern!("81e2u4 486bc2u1"), site) else {
+        warn!("failed to find the layout of \"{}\" near its match", name);
+
+        return;
+    };
+
+    let (mask, entry_size) = (save[1], save[2]);
+
+    // The mask selects the index within a chunk, so it's always one less than a power of two.
+    let Some(chunk_size) = mask.checked_add(1).filter(|size| size.is_power_of_two()) else {
+        warn!("unexpected entity list index mask: {:#X}", mask);
+
+        return;
+    };
+
+    map.insert(format!("{}_chunkSize", name), chunk_size);
+    map.insert(format!("{}_entrySize", name), entry_size);
+}
+
+/// How far into the camera manager its view angles can plausibly be.
+const MAX_VIEW_ANGLES_OFFSET: Rva = 0x1000;
+
+/// Finds where the camera manager holds the view angles from the code right after the match that
+/// copies the pitch and yaw out of it (`movsd xmm0, [rdi + disp]; movsd [rsi], xmm0`), and stores
+/// the RVA of the angles as `dwViewAngles`.
+fn camera_view_angles(
+    view: &PeView,
+    map: &mut BTreeMap<String, Rva>,
+    name: &str,
+    rva: Rva,
+    site: Rva,
+) {
+    let Some(save) = find_near_match(view, pattern!("f20f1087u4 f20f1106"), site) else {
+        warn!(
+            "failed to find the view angles of \"{}\" near its match",
+            name
+        );
+
+        return;
+    };
+
+    let offset = save[1];
+
+    if offset >= MAX_VIEW_ANGLES_OFFSET {
+        warn!("unexpected view angles offset: {:#X}", offset);
+
+        return;
+    }
+
+    map.insert("dwViewAngles".to_string(), rva + offset);
+}
+
+/// Finds the local pawn from the code right after the match that loads it once the local
+/// controller was compared against (`mov rax, [rip + disp]; test rax, rax`), and stores its RVA as
+/// `dwLocalPlayerPawn`.
+fn controller_local_pawn(
+    view: &PeView,
+    map: &mut BTreeMap<String, Rva>,
+    name: &str,
+    _rva: Rva,
+    site: Rva,
+) {
+    let Some(save) = find_near_match(view, pattern!("488b05${'} 4885c0"), site) else {
+        warn!(
+            "failed to find the local pawn of \"{}\" near its match",
+            name
+        );
+
+        return;
+    };
+
+    map.insert("dwLocalPlayerPawn".to_string(), save[1]);
+}
+
+/// The range a bounds-checked entity index can plausibly be limited to.
+const MAX_ENTITY_INDICES: RangeInclusive<Rva> = 0x1000..=0x100000;
+
+/// Derives the bounds of the entity identity array from the check the lookup right after the match
+/// starts with (`cmp edx, max; ja`), and stores the highest valid index and the number of
+/// identities alongside it as `<name>_maxEntityIndex` and `<name>_identityCount`.
+fn entity_system_bounds(
+    view: &PeView,
+    map: &mut BTreeMap<String, Rva>,
+    name: &str,
+    _rva: Rva,
+    site: Rva,
+) {
+    let Some(save) = find_near_match(view, pattern!("81fau4 0f87"), site) else {
+        warn!(
+            "failed to find the entity index bounds of \"{}\" near its match",
+            name
+        );
+
+        return;
+    };
+
+    let max_index = save[1];
+
+    if !MAX_ENTITY_INDICES.contains(&max_index) {
+        warn!("unexpected highest entity index: {:#X}", max_index);
+
+        return;
+    }
+
+    // The identities are allocated in whole chunks, so there's always a power of two of them,
+    // the last of which is reserved for invalid handles.
+    map.insert(format!("{}_maxEntityIndex", name), max_index);
+    map.insert(
+        format!("{}_identityCount", name),
+        (max_index + 1).next_power_of_two(),
+    );
+}
+
+/// Whether the value captured in `slot` by `pat` is an address within the module. `'` saves the
+/// cursor, while every other capture (e.g. `u4`) reads a raw value out of the instruction itself.
+fn saves_cursor(pat: &[Atom], slot: usize) -> bool {
+    pat.iter()
+        .any(|atom| matches!(atom, Atom::Save(index) if *index as usize == slot))
+}
+
+/// The number of values captured by `pat`, not counting the start of the match.
+const fn capture_count(pat: &[Atom]) -> usize {
+    let mut count = 0;
+    let mut i = 0;
+
+    while i < pat.len() {
+        let slot = match pat[i] {
+            Atom::Save(slot)
+            | Atom::ReadI8(slot)
+            | Atom::ReadU8(slot)
+            | Atom::ReadI16(slot)
+            | Atom::ReadU16(slot)
+            | Atom::ReadI32(slot)
+            | Atom::ReadU32(slot) => slot as usize,
+            _ => 0,
+        };
+
+        if slot > count {
+            count = slot;
+        }
+
+        i += 1;
+    }
+
+    count
+}
+
+/// Whether every one of `patterns` captures a value in `slot`. Unless the slot was named
+/// explicitly, they have to capture exactly one value.
+pub(super) const fn valid_captures(patterns: &[&[Atom]], slot: usize, explicit: bool) -> bool {
+    let mut i = 0;
+
+    while i < patterns.len() {
+        let count = capture_count(patterns[i]);
+
+        if slot == 0 || count < slot || (!explicit && count != 1) {
+            return false;
+        }
+
+        i += 1;
+    }
+
+    true
+}
+
+fn is_valid_capture(view: &PeView<'_>, pat: &[Atom], slot: usize, value: Rva) -> bool {
+    if saves_cursor(pat, slot) {
+        value < view.optional_header().SizeOfImage
+    } else {
+        value < MAX_DISPLACEMENT
+    }
+}
+
+fn glob_match(glob: &str, name: &str) -> bool {
+    match glob.split_once('*') {
+        Some((prefix, rest)) => name.strip_prefix(prefix).is_some_and(|name| {
+            name.char_indices()
+                .map(|(i, _)| i)
+                .chain([name.len()])
+                .any(|i| glob_match(rest, &name[i..]))
+        }),
+        None => glob == name,
+    }
+}
+
+#[cfg(feature = "scan")]
+pub(super) fn selected_modules(filter: &OffsetFilter) -> Vec<&'static ModuleEntry> {
+    MODULES
+        .iter()
+        .filter(|(module_name, ..)| filter.includes_module(module_name))
+        .collect()
+}
+
+/// Runs the patterns registered for `module_name` against an already loaded module image, e.g.
+/// one that was dumped to disk.
+///
+/// Returns `None` if no patterns are registered for the module.
+pub fn scan_view(view: PeView<'_>, module_name: &str) -> Option<BTreeMap<String, Rva>> {
+    MODULES
+        .iter()
+        .find(|(name, ..)| name.eq_ignore_ascii_case(module_name))
+        .map(|(name, patterns, _, aliases)| {
+            scan_module(&view, name, patterns, aliases, &ScanOptions::default()).offsets
+        })
+}
+
+/// Like [`scan_view`], but parses the module image from `image` first, widening the headers of
+/// 32-bit images with [`widen_image`].
+pub fn scan_image(
+    image: &[u8],
+    module_name: &str,
+) -> Result<Option<BTreeMap<String, Rva>>, pelite::Error> {
+    if image_bitness(image) == Some(Bitness::Pe32) {
+        return Ok(scan_view(
+            PeView::from_bytes(&widen_image(image)?)?,
+            module_name,
+        ));
+    }
+
+    Ok(scan_view(PeView::from_bytes(image)?, module_name))
+}
+
+/// The size of the header written by [`raw_image`], and the RVA its section starts at.
+#[cfg(feature = "scan")]
+pub(super) const RAW_HEADER_SIZE: usize = 0x1000;
+
+/// Where the section headers start in the headers written by [`write_pe64_headers`].
+const PE64_SECTION_HEADERS: usize = 0x148;
+
+/// The size of an `IMAGE_SECTION_HEADER`, which is the same for both bitnesses.
+const SECTION_HEADER_SIZE: usize = 0x28;
+
+/// The bitness of a PE image, as told by the magic of its optional header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bitness {
+    Pe32,
+    Pe64,
+}
+
+/// Reads the bitness of `image` from its headers, or `None` if they aren't those of a PE image.
+pub fn image_bitness(image: &[u8]) -> Option<Bitness> {
+    let read_u16 = |offset: usize| {
+        image
+            .get(offset..offset + 2)
+            .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+    };
+
+    if read_u16(0)? != 0x5A4D {
+        return None;
+    }
+
+    let nt_headers = u32::from_le_bytes(image.get(0x3C..0x40)?.try_into().ok()?) as usize;
+
+    match read_u16(nt_headers.checked_add(0x18)?)? {
+        0x10B => Some(Bitness::Pe32),
+        0x20B => Some(Bitness::Pe64),
+        _ => None,
+    }
+}
+
+/// Writes the DOS, NT and optional headers of a 64-bit image with `sections` section headers,
+/// which are left for the caller to write at [`PE64_SECTION_HEADERS`].
+fn write_pe64_headers(
+    image: &mut [u8],
+    image_base: u64,
+    sections: u16,
+    alignment: (u32, u32),
+    size_of_headers: u32,
+) {
+    let image_size = image.len() as u32;
+
+    let mut put = |offset: usize, bytes: &[u8]| {
+        image[offset..offset + bytes.len()].copy_from_slice(bytes);
+    };
+
+    // IMAGE_DOS_HEADER
+    put(0x00, b"MZ");
+    put(0x3C, &0x40u32.to_le_bytes());
+
+    // IMAGE_NT_HEADERS64
+    put(0x40, b"PE\0\0");
+    put(0x44, &0x8664u16.to_le_bytes()); // Machine
+    put(0x46, &sections.to_le_bytes()); // NumberOfSections
+    put(0x54, &0xF0u16.to_le_bytes()); // SizeOfOptionalHeader
+    put(0x56, &0x2022u16.to_le_bytes()); // Characteristics
+
+    // IMAGE_OPTIONAL_HEADER64
+    put(0x58, &0x20Bu16.to_le_bytes()); // Magic
+    put(0x70, &image_base.to_le_bytes()); // ImageBase
+    put(0x78, &alignment.0.to_le_bytes()); // SectionAlignment
+    put(0x7C, &alignment.1.to_le_bytes()); // FileAlignment
+    put(0x90, &image_size.to_le_bytes()); // SizeOfImage
+    put(0x94, &size_of_headers.to_le_bytes()); // SizeOfHeaders
+    put(0xC4, &16u32.to_le_bytes()); // NumberOfRvaAndSizes
+}
+
+/// Wraps the raw bytes of a module whose PE header is missing or corrupt, e.g. one that was
+/// manually mapped, in a minimal 64-bit PE image that [`PeView`] can parse.
+///
+/// The header is written over the first [`RAW_HEADER_SIZE`] bytes, and everything after them is
+/// treated as a single executable section. RVAs therefore stay offsets into `buf`, so patterns and
+/// RIP-relative operands resolve the same as they would in the real module.
+///
+/// Returns `None` if `buf` is too small to contain anything past the header.
+#[cfg(feature = "scan")]
+pub(super) fn raw_image(buf: &[u8]) -> Option<Vec<u8>> {
+    let code_size = buf
+        .len()
+        .checked_sub(RAW_HEADER_SIZE)
+        .filter(|size| *size > 0)?
+        .next_multiple_of(RAW_HEADER_SIZE);
+
+    let image_size = RAW_HEADER_SIZE + code_size;
+
+    let mut image = vec![0u8; image_size];
+
+    image[RAW_HEADER_SIZE..buf.len()].copy_from_slice(&buf[RAW_HEADER_SIZE..]);
+
+    let header_size = RAW_HEADER_SIZE as u32;
+
+    write_pe64_headers(
+        &mut image,
+        0x180000000,
+        1,
+        (header_size, header_size),
+        header_size,
+    );
+
+    let mut put = |offset: usize, bytes: &[u8]| {
+        image[offset..offset + bytes.len()].copy_from_slice(bytes);
+    };
+
+    put(0x5C, &(code_size as u32).to_le_bytes()); // SizeOfCode
+    put(0x6C, &header_size.to_le_bytes()); // BaseOfCode
+
+    // IMAGE_SECTION_HEADER
+    put(0x148, b".text\0\0\0");
+    put(0x150, &(code_size as u32).to_le_bytes()); // VirtualSize
+    put(0x154, &header_size.to_le_bytes()); // VirtualAddress
+    put(0x158, &(code_size as u32).to_le_bytes()); // SizeOfRawData
+    put(0x15C, &header_size.to_le_bytes()); // PointerToRawData
+    put(0x16C, &0xE0000020u32.to_le_bytes()); // Characteristics
+
+    Some(image)
+}
+
+/// Rewrites the headers of a mapped 32-bit image as those of a 64-bit one, so it can be scanned
+/// like every other module. The sections, data directories and image base are kept, and nothing
+/// past the headers is touched, so RVAs stay the same.
+///
+/// Patterns are matched byte for byte either way, but RIP-relative operands (`$`) only exist in
+/// 64-bit code, so the patterns of 32-bit modules have to capture absolute addresses instead.
+pub fn widen_image(buf: &[u8]) -> Result<Vec<u8>, pelite::Error> {
+    use pelite::pe32::{self, Pe as _};
+
+    let view = pe32::PeView::from_bytes(buf)?;
+
+    let optional_header = view.optional_header();
+    let sections = view.section_headers();
+
+    let size_of_headers = optional_header.SizeOfHeaders as usize;
+
+    // The headers are rewritten in place, so they must not get any bigger.
+    let headers_end = PE64_SECTION_HEADERS + sections.len() * SECTION_HEADER_SIZE;
+
+    if headers_end > size_of_headers || size_of_headers > buf.len() {
+        return Err(pelite::Error::Bounds);
+    }
+
+    let nt_headers = u32::from_le_bytes(buf[0x3C..0x40].try_into().unwrap()) as usize;
+
+    // The data directories and section headers directly follow the optional header, whose
+    // 32-bit version is 0x10 bytes smaller.
+    let directories = nt_headers + 0x18 + 0x60;
+    let section_headers = directories + 0x80;
+
+    let copy = |image: &mut [u8], from: usize, to: usize, len: usize| {
+        image[to..to + len].copy_from_slice(&buf[from..from + len]);
+    };
+
+    let mut image = buf.to_vec();
+
+    image[..size_of_headers].fill(0);
+
+    write_pe64_headers(
+        &mut image,
+        optional_header.ImageBase as u64,
+        sections.len() as u16,
+        (
+            optional_header.SectionAlignment,
+            optional_header.FileAlignment,
+        ),
+        size_of_headers as u32,
+    );
+
+    copy(&mut image, directories, 0xC8, 0x80);
+    copy(
+        &mut image,
+        section_headers,
+        PE64_SECTION_HEADERS,
+        sections.len() * SECTION_HEADER_SIZE,
+    );
+
+    Ok(image)
+}
+
+/// Scans for every pattern in `patterns` that's included by [`ScanOptions::filter`], one after
+/// another.
+pub(super) fn scan_module(
+    view: &PeView<'_>,
+    module_name: &str,
+    patterns: &PatternTable,
+    aliases: &AliasTable,
+    options: &ScanOptions,
+) -> ModuleScan {
+    let results = selected_patterns(module_name, patterns, options)
+        .into_iter()
+        .map(|(name, entry)| {
+            let (patterns, slot, _, recovery) = &entry;
+
+            let found = scan_patterns(view, name, patterns, *slot, *recovery, options);
+
+            (name, entry, found)
+        })
+        .collect();
+
+    collect_module_scan(view, module_name, results, aliases, options)
+}
+
+/// The patterns registered for `module_name`, merged with the ones in [`ScanOptions::patterns`].
+pub(super) fn selected_patterns<'a>(
+    module_name: &str,
+    patterns: &'a PatternTable,
+    options: &'a ScanOptions,
+) -> Vec<(&'a str, SelectedEntry<'a>)> {
+    let mut selected: BTreeMap<&str, SelectedEntry<'a>> = patterns
+        .entries()
+        .map(|(&name, &(patterns, slot, callback, recovery))| {
+            (name, (patterns.to_vec(), slot, callback, recovery))
+        })
+        .collect();
+
+    let external = options
+        .patterns
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case(module_name))
+        .flat_map(|(_, patterns)| patterns);
+
+    for (name, external) in external {
+        // Loaded patterns always capture a single value, but keep the callback and recovery pattern
+        // of the registered pattern they replace.
+        let (callback, recovery) = patterns
+            .get(name.as_str())
+            .map_or((None, None), |(_, _, callback, recovery)| {
+                (*callback, *recovery)
+            });
+
+        selected.insert(
+            name.as_str(),
+            (vec![external.as_slice()], 1, callback, recovery),
+        );
+    }
+
+    selected
+        .into_iter()
+        .filter(|(name, _)| options.filter.includes_name(name))
+        .collect()
+}
+
+/// Collects the results of scanning for each pattern of a module. Callbacks can modify the map, so
+/// they only run once every pattern has been scanned for.
+pub(super) fn collect_module_scan(
+    view: &PeView<'_>,
+    module_name: &str,
+    results: Vec<(&str, SelectedEntry<'_>, Option<PatternMatch>)>,
+    aliases: &AliasTable,
+    options: &ScanOptions,
+) -> ModuleScan {
+    let mut scan = ModuleScan::default();
+
+    for (name, (patterns, slot, callback, _), found) in results {
+        let Some(found) = found else {
+            scan.missing.insert(name.to_string());
+
+            continue;
+        };
+
+        let is_address = patterns.iter().all(|pat| saves_cursor(pat, slot));
+
+        let sections = MatchSections {
+            site: section_name(view, found.site),
+            value: is_address
+                .then(|| section_name(view, found.value))
+                .flatten(),
+        };
+
+        debug!(
+            "\"{}\" matched in {} ({:#X}), pointing into {}",
+            name,
+            sections.site.as_deref().unwrap_or("no section"),
+            found.site,
+            sections.value.as_deref().unwrap_or("no section"),
+        );
+
+        // Functions are always in code, so a pointer into another section means the pattern
+        // matched something else.
+        if name.starts_with("fn") && is_address && !is_code(view, found.value) {
+            warn!(
+                "function \"{}\" points into {} instead of code",
+                name,
+                sections.value.as_deref().unwrap_or("no section")
+            );
+        }
+
+        // Functions found through a `call` have to be its callee, not its displacement.
+        if name.starts_with("fn") && is_address {
+            let callee = view
+                .derva_slice::<u8>(found.site, 5)
+                .ok()
+                .filter(|code| code[0] == 0xE8)
+                .and_then(|code| {
+                    let disp = i32::from_le_bytes(code[1..].try_into().unwrap());
+
+                    resolve_call_target(view, found.site, disp)
+                })
+                .filter(|callee| *callee != found.value);
+
+            if let Some(callee) = callee {
+                warn!(
+                    "function \"{}\" is {:#X}, but the call it was found through goes to {:#X}",
+                    name, found.value, callee
+                );
+            }
+        }
+
+        scan.sections.insert(name.to_string(), sections);
+
+        scan.offsets.insert(name.to_string(), found.value);
+
+        // Sites are stored before the callback runs, so it can refer to them.
+        if options.record_sites {
+            scan.offsets
+                .insert(format!("{}{}", name, SITE_SUFFIX), found.site);
+        }
+
+        if let Some(count) = found.count {
+            scan.match_counts.insert(name.to_string(), count);
+        }
+
+        if found.recovered {
+            scan.recovered.insert(name.to_string());
+        }
+
+        if let Some(callback) = callback {
+            callback(view, &mut scan.offsets, name, found.value, found.site);
+        }
+    }
+
+    for (name, aliases) in aliases.entries() {
+        if let Some(&value) = scan.offsets.get(*name) {
+            for alias in *aliases {
+                scan.offsets.insert(alias.to_string(), value);
+            }
+        }
+    }
+
+    if options.prologue_len > 0 {
+        scan.prologues = prologues(view, &scan.offsets, options.prologue_len);
+    }
+
+    for (name, value) in &scan.offsets {
+        debug!(
+            "found \"{}\" at {:#X} ({} + {:#X})",
+            name,
+            *value as u64 + view.optional_header().ImageBase,
+            module_name,
+            value
+        );
+    }
+
+    scan
+}
+
+/// The first `len` bytes of the code each function offset points to, as a hex string.
+fn prologues(
+    view: &PeView<'_>,
+    offsets: &BTreeMap<String, Rva>,
+    len: usize,
+) -> BTreeMap<String, String> {
+    offsets
+        .iter()
+        .filter(|(name, _)| name.starts_with("fn") && !name.ends_with(SITE_SUFFIX))
+        .filter_map(|(name, rva)| {
+            let Ok(bytes) = view.derva_slice::<u8>(*rva, len) else {
+                warn!(
+                    "failed to read {} bytes of the prologue of \"{}\"",
+                    len, name
+                );
+
+                return None;
+            };
+
+            Some((name.clone(), hex_bytes(bytes)))
+        })
+        .collect()
+}
+
+/// The section headers containing `rva`.
+fn section_at<'a>(view: &PeView<'a>, rva: Rva) -> Option<&'a IMAGE_SECTION_HEADER> {
+    view.section_headers().iter().find(|section| {
+        let size = section.VirtualSize.max(section.SizeOfRawData);
+
+        (section.VirtualAddress..section.VirtualAddress.saturating_add(size)).contains(&rva)
+    })
+}
+
+/// The name of the section containing `rva`, e.g. `.text`.
+fn section_name(view: &PeView<'_>, rva: Rva) -> Option<String> {
+    section_at(view, rva)?.name().ok().map(str::to_string)
+}
+
+/// Whether `rva` is within an executable section.
+fn is_code(view: &PeView<'_>, rva: Rva) -> bool {
+    section_at(view, rva)
+        .is_some_and(|section| section.Characteristics & IMAGE_SCN_MEM_EXECUTE != 0)
+}
+
+/// Returns the source text of every registered pattern, keyed by offset name, along with the name
+/// of the module it's scanned for in.
+pub fn pattern_sources() -> BTreeMap<&'static str, (&'static str, &'static [&'static str])> {
+    MODULES
+        .iter()
+        .flat_map(|(module_name, _, sources, _)| {
+            sources
+                .entries()
+                .map(move |(&name, &patterns)| (name, (*module_name, patterns)))
+        })
+        .collect()
+}
+
+/// Whether the offset `name` in `module_name` is the RVA of something in the module, rather than
+/// e.g. a struct offset. Returns `false` for offsets that aren't scanned for directly, except for
+/// the sites they were found at.
+pub fn is_address(module_name: &str, name: &str) -> bool {
+    let Some((_, patterns, ..)) = MODULES
+        .iter()
+        .find(|(entry_name, ..)| entry_name.eq_ignore_ascii_case(module_name))
+    else {
+        return false;
+    };
+
+    if let Some(name) = name.strip_suffix(SITE_SUFFIX) {
+        return patterns.contains_key(name);
+    }
+
+    patterns
+        .get(name)
+        .is_some_and(|(patterns, slot, ..)| patterns.iter().all(|pat| saves_cursor(pat, *slot)))
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use anyhow::Result;
+
+    use super::*;
+
+    #[test]
+    fn capture_widths() -> Result<()> {
+        let scan = |code: &[u8], pat: &[Atom]| -> Result<Option<Rva>> {
+            let image = build_image(code);
+            let view = PeView::from_bytes(&image)?;
+
+            Ok(
+                scan_patterns(&view, "test", &[pat], 1, None, &ScanOptions::default())
+                    .map(|found| found.value),
+            )
+        };
+
+        // mov eax, [rax - 8]; ret
+        assert_eq!(
+            scan(&[0x8B, 0x40, 0xF8, 0xC3], pattern!("8b40 u1 c3"))?,
+            Some(0xF8)
+        );
+        assert_eq!(
+            scan(&[0x8B, 0x40, 0xF8, 0xC3], pattern!("8b40 i1 c3"))?,
+            None
+        );
+
+        // mov ax, 0x1234; ret
+        assert_eq!(
+            scan(&[0x66, 0xB8, 0x34, 0x12, 0xC3], pattern!("66b8 u2 c3"))?,
+            Some(0x1234)
+        );
+
+        // mov eax, [rax + 0x1B80]; ret
+        assert_eq!(
+            scan(
+                &[0x8B, 0x80, 0x80, 0x1B, 0x00, 0x00, 0xC3],
+                pattern!("8b80 u4 c3")
+            )?,
+            Some(0x1B80)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn capture_lint() {
+        assert_eq!(capture_count(pattern!("488935${'} 4885f6")), 1);
+        assert_eq!(
+            capture_count(pattern!("8905${'} 488d0d${} ff15${} 488b0d")),
+            1
+        );
+        assert_eq!(capture_count(pattern!("81e2u4 486bc2u1")), 2);
+        assert_eq!(capture_count(pattern!("4885c0 74")), 0);
+
+        assert!(valid_captures(&[pattern!("8b81u4 c3")], 1, false));
+        assert!(!valid_captures(&[pattern!("4885c0 74")], 1, false));
+        assert!(!valid_captures(&[pattern!("81e2u4 486bc2u1")], 1, false));
+        assert!(valid_captures(&[pattern!("81e2u4 486bc2u1")], 2, true));
+        assert!(!valid_captures(&[pattern!("81e2u4 486bc2u1")], 3, true));
+    }
+
+    #[test]
+    fn capture_bounds() -> Result<()> {
+        // lea rax, [rip + disp]; ret; int3 * 8; push rbx
+        let code = |disp: i32| {
+            let mut code = vec![0x48, 0x8D, 0x05];
+
+            code.extend(disp.to_le_bytes());
+            code.push(0xC3);
+            code.extend([0xCC; 8]);
+            code.extend([0x40, 0x53]);
+            code
+        };
+
+        let image = build_image(&code(0x10));
+        let ModuleScan { offsets, .. } =
+            input_system::offsets(PeView::from_bytes(&image)?, &ScanOptions::default());
+
+        assert_eq!(offsets.get("dwInputSystem"), Some(&0x1017));
+
+        let image = build_image(&code(0x7FFF0000));
+        let ModuleScan {
+            offsets, missing, ..
+        } = input_system::offsets(PeView::from_bytes(&image)?, &ScanOptions::default());
+
+        assert!(offsets.is_empty());
+        assert!(missing.contains("dwInputSystem"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn view_matrix() -> Result<()> {
+        // lea rax, [r15 + disp]; imul r14, r12, ...
+        let code = |disp: u32| {
+            let mut code = vec![0x49, 0x8D, 0x87];
+
+            code.extend(disp.to_le_bytes());
+            code.extend([0x4D, 0x69, 0xF4]);
+            code
+        };
+
+        let image = build_image(&code(0x1B80));
+        let ModuleScan { offsets, .. } =
+            client::offsets(PeView::from_bytes(&image)?, &ScanOptions::default());
+
+        assert_eq!(offsets.get("dwViewMatrix"), Some(&0x1B80));
+
+        let image = build_image(&code(0x200000));
+        let ModuleScan {
+            offsets, missing, ..
+        } = client::offsets(PeView::from_bytes(&image)?, &ScanOptions::default());
+
+        assert!(!offsets.contains_key("dwViewMatrix"));
+        assert!(missing.contains("dwViewMatrix"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn match_counts() -> Result<()> {
+        // lea rax, [rip + 0x10]; ret; int3 * 8; push rbx, twice.
+        let code = [0x48, 0x8D, 0x05, 0x10, 0x00, 0x00, 0x00, 0xC3]
+            .into_iter()
+            .chain([0xCC; 8])
+            .chain([0x40, 0x53])
+            .collect::<Vec<_>>()
+            .repeat(2);
+
+        let image = build_image(&code);
+
+        let options = ScanOptions {
+            count_matches: true,
+            ..Default::default()
+        };
+
+        let ModuleScan {
+            offsets,
+            match_counts,
+            ..
+        } = input_system::offsets(PeView::from_bytes(&image)?, &options);
+
+        assert_eq!(offsets.get("dwInputSystem"), Some(&0x1017));
+        assert_eq!(match_counts.get("dwInputSystem"), Some(&2));
+
+        Ok(())
+    }
+
+    #[test]
+    fn match_sites() -> Result<()> {
+        // int3 * 4; lea rax, [rip + 0x10]; ret; int3 * 8; push rbx
+        let code = [0xCC; 4]
+            .into_iter()
+            .chain([0x48, 0x8D, 0x05, 0x10, 0x00, 0x00, 0x00, 0xC3])
+            .chain([0xCC; 8])
+            .chain([0x40, 0x53])
+            .collect::<Vec<_>>();
+
+        let image = build_image(&code);
+
+        let options = ScanOptions {
+            record_sites: true,
+            ..Default::default()
+        };
+
+        let ModuleScan { offsets, .. } =
+            input_system::offsets(PeView::from_bytes(&image)?, &options);
+
+        assert_eq!(offsets.get("dwInputSystem"), Some(&0x101B));
+        assert_eq!(offsets.get("dwInputSystem_site"), Some(&0x1004));
+
+        assert!(is_address("inputsystem.dll", "dwInputSystem_site"));
+        assert!(!is_address("inputsystem.dll", "dwFoo_site"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn recovery_pattern() -> Result<()> {
+        // lea rax, [rip + disp]; ret; int3 * 8; push rdi
+        let mut code = vec![0x48, 0x8D, 0x05, 0x10, 0x00, 0x00, 0x00, 0xC3];
+
+        code.extend([0xCC; 8]);
+        code.extend([0x40, 0x57]);
+
+        let image = build_image(&code);
+
+        let ModuleScan {
+            offsets,
+            missing,
+            recovered,
+            ..
+        } = input_system::offsets(PeView::from_bytes(&image)?, &ScanOptions::default());
+
+        assert_eq!(offsets.get("dwInputSystem"), Some(&0x1017));
+        assert!(!missing.contains("dwInputSystem"));
+        assert!(recovered.contains("dwInputSystem"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn missing_pattern() -> Result<()> {
+        let image = build_image(&[0xCC; 0x20]);
+        let view = PeView::from_bytes(&image)?;
+
+        let ModuleScan {
+            offsets, missing, ..
+        } = input_system::offsets(view, &ScanOptions::default());
+
+        assert!(offsets.is_empty());
+        assert!(missing.contains("dwInputSystem"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn negative_displacement() -> Result<()> {
+        // mov [rip - 0x807], rdx; mov [rdx + 8], rax, storing to a global before the code.
+        let code = |disp: i32| {
+            let mut code = vec![0x48, 0x89, 0x15];
+
+            code.extend(disp.to_le_bytes());
+            code.extend([0x48, 0x89, 0x42, 0x08]);
+            code
+        };
+
+        let image = build_image(&code(-0x807));
+        let ModuleScan { offsets, .. } =
+            client::offsets(PeView::from_bytes(&image)?, &ScanOptions::default());
+
+        assert_eq!(offsets.get("dwGlobalVars"), Some(&0x800));
+
+        // Pointing before the start of the image must not wrap around to a huge RVA.
+        let image = build_image(&code(-0x2000));
+        let ModuleScan {
+            offsets, missing, ..
+        } = client::offsets(PeView::from_bytes(&image)?, &ScanOptions::default());
+
+        assert!(!offsets.contains_key("dwGlobalVars"));
+        assert!(missing.contains("dwGlobalVars"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn rip_relative() -> Result<()> {
+        // mov rax, [rip + 0x100]; mov rax, [rip - 0x800]; mov rax, [rip - 0x2000]
+        let code = [
+            0x48, 0x8B, 0x05, 0x00, 0x01, 0x00, 0x00, //
+            0x48, 0x8B, 0x05, 0x00, 0xF8, 0xFF, 0xFF, //
+            0x48, 0x8B, 0x05, 0x00, 0xE0, 0xFF, 0xFF,
+        ];
+
+        let image = build_image(&code);
+        let view = PeView::from_bytes(&image)?;
+
+        assert_eq!(resolve_rip(&view, 0x1000, 3, 7), Some(0x1107));
+        assert_eq!(resolve_rip(&view, 0x1007, 3, 7), Some(0x80E));
+        assert_eq!(resolve_rip(&view, 0x100E, 3, 7), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn call_target() -> Result<()> {
+        // call 0x1100; test rax, rax; jz ...; mov rax, [rax + 8]; lea rcx, ...
+        let mut code = vec![0xE8, 0xFB, 0x00, 0x00, 0x00];
+
+        code.extend([
+            0x48, 0x85, 0xC0, 0x74, 0x05, 0x48, 0x8B, 0x40, 0x08, 0x48, 0x8D, 0x0D,
+        ]);
+        code.resize(0x100, 0xCC);
+
+        // The function being called.
+        code.extend([0x48, 0x83, 0xEC, 0x28]);
+
+        let image = build_image(&code);
+        let view = PeView::from_bytes(&image)?;
+
+        assert_eq!(resolve_call_target(&view, 0x1000, 0xFB), Some(0x1100));
+        assert_eq!(resolve_call_target(&view, 0x1000, -0x1005), Some(0));
+        assert_eq!(resolve_call_target(&view, 0x1000, -0x1006), None);
+        assert_eq!(resolve_call_target(&view, 0x1000, 0x1000), None);
+
+        let ModuleScan {
+            offsets, sections, ..
+        } = client::offsets(view, &ScanOptions::default());
+
+        assert_eq!(offsets.get("fnGetCmd"), Some(&0x1100));
+
+        assert_eq!(
+            sections.get("fnGetCmd"),
+            Some(&MatchSections {
+                site: Some(".text".to_string()),
+                value: Some(".text".to_string()),
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn prologues() -> Result<()> {
+        // call 0x1100; test rax, rax; jz ...; mov rax, [rax + 8]; lea rcx, ...
+        let mut code = vec![0xE8, 0xFB, 0x00, 0x00, 0x00];
+
+        code.extend([
+            0x48, 0x85, 0xC0, 0x74, 0x05, 0x48, 0x8B, 0x40, 0x08, 0x48, 0x8D, 0x0D,
+        ]);
+        code.resize(0x100, 0xCC);
+
+        // sub rsp, 0x28; xor eax, eax
+        code.extend([0x48, 0x83, 0xEC, 0x28, 0x31, 0xC0]);
+
+        let image = build_image(&code);
+        let view = PeView::from_bytes(&image)?;
+
+        let options = ScanOptions {
+            prologue_len: 4,
+            record_sites: true,
+            ..Default::default()
+        };
+
+        let ModuleScan { prologues, .. } = client::offsets(view, &options);
+
+        assert_eq!(
+            prologues,
+            BTreeMap::from([("fnGetCmd".to_string(), "48 83 EC 28".to_string())])
+        );
+
+        let ModuleScan { prologues, .. } = client::offsets(view, &ScanOptions::default());
+
+        assert!(prologues.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn entity_list_layout() -> Result<()> {
+        // mov [rip + 0x100], rsi; test rsi, rsi; and edx, 0x1FF; imul rax, rdx, 0x78
+        let code = [
+            0x48, 0x89, 0x35, 0x00, 0x01, 0x00, 0x00, 0x48, 0x85, 0xF6, //
+            0x81, 0xE2, 0xFF, 0x01, 0x00, 0x00, 0x48, 0x6B, 0xC2, 0x78,
+        ];
+
+        let image = build_image(&code);
+        let ModuleScan { offsets, .. } =
+            client::offsets(PeView::from_bytes(&image)?, &ScanOptions::default());
+
+        assert_eq!(offsets.get("dwEntityList"), Some(&0x1107));
+        assert_eq!(offsets.get("dwEntityList_chunkSize"), Some(&0x200));
+        assert_eq!(offsets.get("dwEntityList_entrySize"), Some(&0x78));
+
+        // Indexing code too far from the match belongs to something else.
+        let mut far = code[..10].to_vec();
+
+        far.resize(0x200, 0xCC);
+        far.extend(&code[10..]);
+
+        let image = build_image(&far);
+        let ModuleScan { offsets, .. } =
+            client::offsets(PeView::from_bytes(&image)?, &ScanOptions::default());
+
+        assert_eq!(offsets.get("dwEntityList"), Some(&0x1107));
+        assert!(!offsets.contains_key("dwEntityList_chunkSize"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn entity_system_bounds() -> Result<()> {
+        // mov rsi, [rip + 0x100]; mov [rsp + 0x50], r14; mov [rsp + 0x58], r15;
+        // cmp edx, 0x7FFE; ja +0
+        let code = [
+            0x48, 0x8B, 0x35, 0x00, 0x01, 0x00, 0x00, //
+            0x4C, 0x89, 0xB4, 0x24, 0x50, 0x00, 0x00, 0x00, //
+            0x4C, 0x89, 0xBC, 0x24, 0x58, 0x00, 0x00, 0x00, //
+            0x81, 0xFA, 0xFE, 0x7F, 0x00, 0x00, 0x0F, 0x87, 0x00, 0x00, 0x00, 0x00,
+        ];
+
+        let image = build_image(&code);
+        let ModuleScan { offsets, .. } =
+            client::offsets(PeView::from_bytes(&image)?, &ScanOptions::default());
+
+        assert_eq!(offsets.get("dwGameEntitySystem"), Some(&0x1107));
+        assert_eq!(
+            offsets.get("dwGameEntitySystem_maxEntityIndex"),
+            Some(&0x7FFE)
+        );
+        assert_eq!(
+            offsets.get("dwGameEntitySystem_identityCount"),
+            Some(&0x8000)
+        );
+
+        // A bounds check too far from the match belongs to something else.
+        let mut far = code[..23].to_vec();
+
+        far.resize(0x200, 0xCC);
+        far.extend(&code[23..]);
+
+        let image = build_image(&far);
+        let ModuleScan { offsets, .. } =
+            client::offsets(PeView::from_bytes(&image)?, &ScanOptions::default());
+
+        assert_eq!(offsets.get("dwGameEntitySystem"), Some(&0x1107));
+        assert!(!offsets.contains_key("dwGameEntitySystem_maxEntityIndex"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn camera_view_angles() -> Result<()> {
+        // lea rdi, [rip + 0x100]; mov ebx, ecx; movsd xmm0, [rdi + 0x48]; movsd [rsi], xmm0
+        let code = [
+            0x48, 0x8D, 0x3D, 0x00, 0x01, 0x00, 0x00, 0x8B, 0xD9, //
+            0xF2, 0x0F, 0x10, 0x87, 0x48, 0x00, 0x00, 0x00, 0xF2, 0x0F, 0x11, 0x06,
+        ];
+
+        let image = build_image(&code);
+        let ModuleScan { offsets, .. } =
+            client::offsets(PeView::from_bytes(&image)?, &ScanOptions::default());
+
+        assert_eq!(offsets.get("dwCCitadelCameraManager"), Some(&0x1107));
+        assert_eq!(offsets.get("dwViewAngles"), Some(&0x114F));
+
+        // A copy too far from the match belongs to something else.
+        let mut far = code[..9].to_vec();
+
+        far.resize(0x200, 0xCC);
+        far.extend(&code[9..]);
+
+        let image = build_image(&far);
+        let ModuleScan { offsets, .. } =
+            client::offsets(PeView::from_bytes(&image)?, &ScanOptions::default());
+
+        assert_eq!(offsets.get("dwCCitadelCameraManager"), Some(&0x1107));
+        assert!(!offsets.contains_key("dwViewAngles"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn performance_counter() -> Result<()> {
+        // sub rax, [rip + 0x100]; cvtsi2sd xmm0, rax; mulsd xmm0, [rip + 0x200]
+        let image = build_image(&[
+            0x48, 0x2B, 0x05, 0x00, 0x01, 0x00, 0x00, //
+            0xF2, 0x48, 0x0F, 0x2A, 0xC0, //
+            0xF2, 0x0F, 0x59, 0x05, 0x00, 0x02, 0x00, 0x00,
+        ]);
+
+        let ModuleScan { offsets, .. } =
+            tier0::offsets(PeView::from_bytes(&image)?, &ScanOptions::default());
+
+        assert_eq!(offsets.get("dwPerformanceCounterStart"), Some(&0x1107));
+        assert_eq!(offsets.get("dwPerformanceCounterToSeconds"), Some(&0x1214));
+
+        Ok(())
+    }
+
+    #[test]
+    fn accessor() -> Result<()> {
+        // mov rax, [rip + 0x100]; ret; xor eax, eax; ret
+        let image = build_image(&[
+            0x48, 0x8B, 0x05, 0x00, 0x01, 0x00, 0x00, 0xC3, 0x31, 0xC0, 0xC3,
+        ]);
+        let view = PeView::from_bytes(&image)?;
+
+        let mut map = BTreeMap::new();
+
+        follow_accessor(&view, &mut map, "dwMidbossManager", 0x1000, 0x1000);
+
+        assert_eq!(map.get("dwMidbossManager"), Some(&0x1107));
+
+        map.insert("dwObjectiveManager".to_string(), 0x1008);
+
+        follow_accessor(&view, &mut map, "dwObjectiveManager", 0x1008, 0x1008);
+
+        assert!(!map.contains_key("dwObjectiveManager"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn pe32_image() -> Result<()> {
+        let mut image = vec![0u8; 0x2000];
+
+        let mut put = |offset: usize, bytes: &[u8]| {
+            image[offset..offset + bytes.len()].copy_from_slice(bytes);
+        };
+
+        // IMAGE_DOS_HEADER
+        put(0x00, b"MZ");
+        put(0x3C, &0x40u32.to_le_bytes());
+
+        // IMAGE_NT_HEADERS32
+        put(0x40, b"PE\0\0");
+        put(0x44, &0x14Cu16.to_le_bytes()); // Machine
+        put(0x46, &1u16.to_le_bytes()); // NumberOfSections
+        put(0x54, &0xE0u16.to_le_bytes()); // SizeOfOptionalHeader
+        put(0x56, &0x2102u16.to_le_bytes()); // Characteristics
+
+        // IMAGE_OPTIONAL_HEADER32
+        put(0x58, &0x10Bu16.to_le_bytes()); // Magic
+        put(0x74, &0x10000000u32.to_le_bytes()); // ImageBase
+        put(0x78, &0x1000u32.to_le_bytes()); // SectionAlignment
+        put(0x7C, &0x1000u32.to_le_bytes()); // FileAlignment
+        put(0x90, &0x2000u32.to_le_bytes()); // SizeOfImage
+        put(0x94, &0x1000u32.to_le_bytes()); // SizeOfHeaders
+        put(0xB4, &16u32.to_le_bytes()); // NumberOfRvaAndSizes
+
+        // IMAGE_SECTION_HEADER
+        put(0x138, b".text\0\0\0");
+        put(0x140, &0x1000u32.to_le_bytes()); // VirtualSize
+        put(0x144, &0x1000u32.to_le_bytes()); // VirtualAddress
+        put(0x148, &0x1000u32.to_le_bytes()); // SizeOfRawData
+        put(0x14C, &0x1000u32.to_le_bytes()); // PointerToRawData
+        put(0x15C, &0x60000020u32.to_le_bytes()); // Characteristics
+
+        // mov eax, [0x10001100]; ret
+        put(0x1000, &[0xA1, 0x00, 0x11, 0x00, 0x10, 0xC3]);
+
+        assert_eq!(image_bitness(&image), Some(Bitness::Pe32));
+        assert!(PeView::from_bytes(&image).is_err());
+
+        let wide = widen_image(&image)?;
+        let view = PeView::from_bytes(&wide)?;
+
+        assert_eq!(image_bitness(&wide), Some(Bitness::Pe64));
+        assert_eq!(view.optional_header().ImageBase, 0x10000000);
+        assert_eq!(section_name(&view, 0x1000).as_deref(), Some(".text"));
+        assert!(is_code(&view, 0x1000));
+        assert_eq!(wide[0x1000..], image[0x1000..]);
+
+        let pat = pattern!("a1u4 c3");
+        let mut save = vec![0; save_len(pat)];
+
+        assert!(view.scanner().finds_code(pat, &mut save));
+        assert_eq!(save[1], 0x10001100);
+
+        Ok(())
+    }
+
+    #[test]
+    fn glob() {
+        assert!(glob_match("dwEntityList", "dwEntityList"));
+        assert!(glob_match("dw*", "dwEntityList"));
+        assert!(glob_match("*List", "dwEntityList"));
+        assert!(glob_match("dw*Ent*y*", "dwEntityList"));
+        assert!(!glob_match("dw*Matrix", "dwEntityList"));
+        assert!(!glob_match("dwEntity", "dwEntityList"));
+    }
+
+    #[test]
+    fn pattern_text() {
+        let sources = pattern_sources();
+
+        assert_eq!(
+            sources["dwEntityList"],
+            ("client.dll", &[r#"pattern!("488935${'} 4885f6")"#][..])
+        );
+        assert_eq!(sources["dwBuildNumber"].0, "engine2.dll");
+        assert!(!sources.contains_key("dwLocalPlayerPawn"));
+    }
+
+    #[test]
+    fn client_fixture() -> Result<()> {
+        let (image, starts) = fixture(&[
+            // cmp rsi, [rip + 0x100]; jnz ...; mov rax, [rip + 0x100]; test rax, rax
+            [
+                rip(&[0x48, 0x3B, 0x35], &[0x75, 0x10]),
+                rip(&[0x48, 0x8B, 0x05], &[0x48, 0x85, 0xC0]),
+            ]
+            .concat(),
+            // lea rax, [rip + 0x100]; ret; int3 * 8; sub rsp, 0x28; mov ecx, ...
+            rip(
+                &[0x48, 0x8D, 0x05],
+                &[
+                    [0xC3].as_slice(),
+                    &[0xCC; 8],
+                    &[0x48, 0x83, 0xEC, 0x28, 0x8B, 0x0D],
+                ]
+                .concat(),
+            ),
+            // mov rcx, [rip + 0x100]; test rcx, rcx; jz ...; mov eax, [rcx + ...]
+            rip(
+                &[0x48, 0x8B, 0x0D],
+                &[0x48, 0x85, 0xC9, 0x74, 0x10, 0x8B, 0x81],
+            ),
+        ]);
+
+        let offsets = scan_image(&image, "client.dll")?.unwrap();
+
+        assert_eq!(
+            offsets.get("dwLocalPlayerController"),
+            Some(&(starts[0] + 0x107))
+        );
+        assert_eq!(offsets.get("dwLocalPlayerPawn"), Some(&(starts[0] + 0x110)));
+        assert_eq!(offsets.get("dwPrediction"), Some(&(starts[1] + 0x107)));
+        assert_eq!(offsets.get("dwGameRules"), Some(&(starts[2] + 0x107)));
+        assert!(!offsets.contains_key("dwEntityList"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn engine2_fixture() -> Result<()> {
+        let (image, starts) = fixture(&[
+            // mov [rip + 0x100], eax; lea rcx, [rip + 0x100]; call [rip + 0x100]; mov rcx, ...
+            [
+                rip(&[0x89, 0x05], &[]),
+                rip(&[0x48, 0x8D, 0x0D], &[]),
+                rip(&[0xFF, 0x15], &[0x48, 0x8B, 0x0D]),
+            ]
+            .concat(),
+            // mov eax, [rip + 0x100]; mov [rdi], eax
+            rip(&[0x8B, 0x05], &[0x89, 0x07]),
+            // mov eax, [rip + 0x100]; mov [rbx], eax
+            rip(&[0x8B, 0x05], &[0x89, 0x03]),
+        ]);
+
+        // Module names are matched case-insensitively.
+        let offsets = scan_image(&image, "ENGINE2.DLL")?.unwrap();
+
+        assert_eq!(offsets.get("dwBuildNumber"), Some(&(starts[0] + 0x106)));
+        assert_eq!(offsets.get("dwWindowWidth"), Some(&(starts[1] + 0x106)));
+        assert_eq!(offsets.get("dwWindowHeight"), Some(&(starts[2] + 0x106)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn schema_system_fixture() -> Result<()> {
+        // lea r14, [rip + 0x100]; movaps xmm0, ...
+        let (image, starts) = fixture(&[rip(&[0x4C, 0x8D, 0x35], &[0x0F, 0x28, 0x45])]);
+
+        let offsets = scan_view(PeView::from_bytes(&image)?, "schemasystem.dll").unwrap();
+
+        assert_eq!(
+            offsets,
+            BTreeMap::from([("dwSchemaSystem".to_string(), starts[0] + 0x107)])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn aliases() -> Result<()> {
+        static ALIASES: AliasTable = phf_map! {
+            "dwSchemaSystem" => &["dwSchemaSystemInterface"],
+        };
+
+        let (image, starts) = fixture(&[rip(&[0x4C, 0x8D, 0x35], &[0x0F, 0x28, 0x45])]);
+
+        let scan = scan_module(
+            &PeView::from_bytes(&image)?,
+            "schemasystem.dll",
+            &schema_system::PATTERNS,
+            &ALIASES,
+            &ScanOptions::default(),
+        );
+
+        assert_eq!(
+            scan.offsets,
+            BTreeMap::from([
+                ("dwSchemaSystem".to_string(), starts[0] + 0x107),
+                ("dwSchemaSystemInterface".to_string(), starts[0] + 0x107),
+            ])
+        );
+
+        // An alias must not shadow an offset that is still scanned for.
+        for (module_name, patterns, _, aliases) in MODULES {
+            for alias in aliases.values().flat_map(|aliases| aliases.iter()) {
+                assert!(
+                    !patterns.contains_key(alias),
+                    "{} in {} is both an alias and an offset",
+                    alias,
+                    module_name
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_image() -> Result<()> {
+        assert!(scan_image(&[0; 0x40], "client.dll").is_err());
+        assert!(scan_image(&build_image(&[0xC3]), "unknown.dll")?.is_none());
+
+        Ok(())
+    }
+
+    /// An instruction whose RIP-relative operand points 0x100 bytes past its end, followed by `rest`.
+    fn rip(opcode: &[u8], rest: &[u8]) -> Vec<u8> {
+        [opcode, &0x100u32.to_le_bytes(), rest].concat()
+    }
+
+    /// Builds an image out of `snippets`, separated by padding, along with the RVA each snippet
+    /// starts at.
+    fn fixture(snippets: &[Vec<u8>]) -> (Vec<u8>, Vec<Rva>) {
+        let mut code = Vec::new();
+        let mut starts = Vec::new();
+
+        for snippet in snippets {
+            starts.push(0x1000 + code.len() as Rva);
+
+            code.extend(snippet);
+            code.extend([0x90; 8]);
+        }
+
+        (build_image(&code), starts)
+    }
+
+    /// Builds a minimal mapped 64-bit PE image with a single executable section containing `code`.
+    pub(in crate::analysis) fn build_image(code: &[u8]) -> Vec<u8> {
+        let mut image = vec![0u8; RAW_HEADER_SIZE];
+
+        image.extend(code);
+
+        raw_image(&image).unwrap()
+    }
+}
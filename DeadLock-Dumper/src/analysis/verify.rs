@@ -0,0 +1,187 @@
+use std::ops::RangeInclusive;
+
+use anyhow::{Result, bail};
+
+use log::{debug, warn};
+
+use memflow::prelude::v1::*;
+
+use phf::{Map, phf_map};
+
+use super::OffsetMap;
+
+/// An invariant of a resolved offset, returning why it doesn't hold.
+pub type Check = fn(&Target<'_>) -> Result<(), String>;
+
+/// What a [`Check`] gets to look at.
+pub struct Target<'a> {
+    /// The sections of the module the offset belongs to.
+    pub sections: &'a [SectionInfo],
+    /// The absolute address of the offset.
+    pub address: Address,
+    /// The 8 bytes read at `address`.
+    pub value: u64,
+}
+
+/// The checks run on the offsets with these names after they are scanned for.
+pub static CHECKS: Map<&'static str, Check> = phf_map! {
+    "dwBuildNumber" => plausible_build_number,
+    "dwLocalPlayerController" => in_data_section,
+};
+
+/// Build numbers outside of this range are most likely read from the wrong address.
+const BUILD_NUMBERS: RangeInclusive<u32> = 1_000..=99_999;
+
+/// Runs the registered [`CHECKS`] against the live process, warning about every offset that fails
+/// one, and skipping the modules whose sections can't be read. If `strict` is set, any failure is
+/// an error instead.
+pub fn verify_offsets<P: Process + MemoryView>(
+    process: &mut P,
+    offsets: &OffsetMap,
+    strict: bool,
+) -> Result<()> {
+    let mut failed = Vec::new();
+
+    for (module_name, offsets) in offsets {
+        let checks: Vec<_> = offsets
+            .iter()
+            .filter_map(|(name, rva)| Some((name, *rva, CHECKS.get(name.as_str())?)))
+            .collect();
+
+        if checks.is_empty() {
+            continue;
+        }
+
+        let Ok(module) = process.module_by_name(module_name) else {
+            debug!("{} isn't loaded, not verifying its offsets", module_name);
+
+            continue;
+        };
+
+        let sections = match process.module_section_list(&module) {
+            Ok(sections) => sections,
+            Err(err) if strict => {
+                bail!("failed to read the sections of {}: {}", module_name, err)
+            }
+            Err(err) => {
+                warn!(
+                    "failed to read the sections of {}, not verifying its offsets: {}",
+                    module_name, err
+                );
+
+                continue;
+            }
+        };
+
+        for (name, rva, check) in checks {
+            let address = module.base + rva;
+
+            let result = process
+                .read::<u64>(address)
+                .data_part()
+                .map_err(|err| format!("failed to read {:#X}: {}", address.to_umem(), err))
+                .and_then(|value| {
+                    check(&Target {
+                        sections: &sections,
+                        address,
+                        value,
+                    })
+                });
+
+            if let Err(reason) = result {
+                warn!("{}::{} failed verification: {}", module_name, name, reason);
+
+                failed.push(name.clone());
+            }
+        }
+    }
+
+    if strict && !failed.is_empty() {
+        bail!("offset(s) failed verification: {}", failed.join(", "));
+    }
+
+    Ok(())
+}
+
+/// The offset is a global variable, which lives in the `.data` section.
+fn in_data_section(target: &Target<'_>) -> Result<(), String> {
+    let section = target
+        .sections
+        .iter()
+        .find(|section| (section.base..section.base + section.size).contains(&target.address));
+
+    match section {
+        Some(section) if &*section.name == ".data" => Ok(()),
+        Some(section) => Err(format!("points into {}, not .data", section.name)),
+        None => Err("doesn't point into any section".to_string()),
+    }
+}
+
+fn plausible_build_number(target: &Target<'_>) -> Result<(), String> {
+    let build_number = target.value as u32;
+
+    if BUILD_NUMBERS.contains(&build_number) {
+        Ok(())
+    } else {
+        Err(format!("{} is not a plausible build number", build_number))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::analysis::pattern_sources;
+
+    fn sections() -> Vec<SectionInfo> {
+        [(".text", 0x180001000u64), (".data", 0x180002000u64)]
+            .into_iter()
+            .map(|(name, base)| SectionInfo {
+                name: name.into(),
+                base: base.into(),
+                size: 0x1000,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn checks_are_registered() {
+        let sources = pattern_sources();
+
+        for name in CHECKS.keys() {
+            assert!(sources.contains_key(*name), "{} isn't scanned for", name);
+        }
+    }
+
+    #[test]
+    fn data_section() {
+        let sections = sections();
+
+        let check = |address: u64| {
+            in_data_section(&Target {
+                sections: &sections,
+                address: address.into(),
+                value: 0,
+            })
+        };
+
+        assert!(check(0x180002010).is_ok());
+        assert!(check(0x180001010).is_err());
+        assert!(check(0x180003000).is_err());
+    }
+
+    #[test]
+    fn build_number() {
+        let check = |value: u64| {
+            plausible_build_number(&Target {
+                sections: &[],
+                address: Address::NULL,
+                value,
+            })
+        };
+
+        assert!(check(5_800).is_ok());
+        assert!(check(0).is_err());
+        assert!(check(0xCCCCCCCC).is_err());
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::BTreeMap;
+#[cfg(feature = "scan")]
+use std::collections::{HashMap, HashSet};
+#[cfg(feature = "scan")]
+use std::ops::Range;
+
+#[cfg(feature = "scan")]
+use anyhow::Result;
+
+#[cfg(feature = "scan")]
+use log::debug;
+
+#[cfg(feature = "scan")]
+use memflow::prelude::v1::*;
+
+#[cfg(feature = "scan")]
+use pelite::image::IMAGE_SCN_MEM_EXECUTE;
+use pelite::pe64::Rva;
+#[cfg(feature = "scan")]
+use pelite::pe64::{Pe, PeView};
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "scan")]
+use super::SchemaMap;
+
+pub type VTableMap = BTreeMap<String, BTreeMap<String, VTable>>;
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+pub struct VTable {
+    /// The RVA of the first method pointer.
+    pub rva: Rva,
+    /// The number of methods, i.e. consecutive pointers into an executable section.
+    pub methods: usize,
+}
+
+/// The modules whose classes are looked up. Reading a module's whole image is expensive, and the
+/// client classes are the ones worth hooking.
+#[cfg(feature = "scan")]
+const VTABLE_MODULES: &[&str] = &["client.dll"];
+
+/// Finds the primary vtable of every schema class declared by the modules in [`VTABLE_MODULES`],
+/// through the RTTI the compiler emits for polymorphic classes.
+///
+/// The schema system doesn't describe methods, so only how many there are is known, not their
+/// names.
+#[cfg(feature = "scan")]
+pub fn vtables<P: Process + MemoryView>(process: &mut P, schemas: &SchemaMap) -> Result<VTableMap> {
+    let mut map = VTableMap::new();
+
+    for module_name in VTABLE_MODULES {
+        let Some((classes, _)) = schemas.get(*module_name) else {
+            continue;
+        };
+
+        let module = process.module_by_name(module_name)?;
+
+        let buf = process
+            .read_raw(module.base, module.size as _)
+            .data_part()?;
+
+        let view = PeView::from_bytes(&buf)?;
+
+        let class_names: HashSet<_> = classes.iter().map(|class| class.name.as_str()).collect();
+
+        let vtables = find_vtables(view, module.base.to_umem(), &class_names);
+
+        debug!(
+            "found vtables for {} of {} class(es) in {}",
+            vtables.len(),
+            classes.len(),
+            module_name
+        );
+
+        map.insert(module_name.to_string(), vtables);
+    }
+
+    Ok(map)
+}
+
+/// Finds the vtables of `class_names` in a module image mapped at `base`.
+///
+/// Every polymorphic class has a type descriptor holding its mangled name, which is referenced by
+/// the complete object locator of each of its vtables. A pointer to the locator is stored right
+/// before the first method pointer of the vtable.
+#[cfg(feature = "scan")]
+fn find_vtables(
+    view: PeView<'_>,
+    base: u64,
+    class_names: &HashSet<&str>,
+) -> BTreeMap<String, VTable> {
+    let image = view.image();
+
+    let read_u32 = |offset: usize| {
+        image
+            .get(offset..offset + 4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+    };
+
+    let read_u64 = |offset: usize| {
+        image
+            .get(offset..offset + 8)
+            .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+    };
+
+    // The mangled name (`.?AV<name>@@` for classes, `.?AU<name>@@` for structs) is stored 0x10
+    // bytes into the type descriptor, which is 8-byte aligned.
+    let descriptors: HashMap<Rva, &str> = image
+        .windows(4)
+        .enumerate()
+        .filter(|(offset, window)| {
+            offset % 8 == 0 && matches!(window, [b'.', b'?', b'A', b'V' | b'U'])
+        })
+        .filter_map(|(offset, _)| {
+            let name = &image[offset + 4..];
+            let len = name.windows(3).position(|end| end == b"@@\0")?;
+            let name = class_names.get(std::str::from_utf8(&name[..len]).ok()?)?;
+
+            Some((offset.checked_sub(0x10)? as Rva, *name))
+        })
+        .collect();
+
+    // Complete object locators of vtables at offset 0 in the class, i.e. not those of secondary
+    // base classes. With 64-bit RTTI, the locator holds its own RVA.
+    let locators: HashMap<u64, &str> = (0..image.len())
+        .step_by(4)
+        .filter(|offset| {
+            read_u32(*offset) == Some(1)
+                && read_u32(offset + 0x4) == Some(0)
+                && read_u32(offset + 0x14) == Some(*offset as u32)
+        })
+        .filter_map(|offset| {
+            let name = descriptors.get(&read_u32(offset + 0xC)?)?;
+
+            Some((base + offset as u64, *name))
+        })
+        .collect();
+
+    let code: Vec<Range<u64>> = view
+        .section_headers()
+        .iter()
+        .filter(|section| section.Characteristics & IMAGE_SCN_MEM_EXECUTE != 0)
+        .map(|section| {
+            let start = section.VirtualAddress as u64;
+
+            start..start + section.VirtualSize as u64
+        })
+        .collect();
+
+    let is_method = |ptr: u64| {
+        ptr.checked_sub(base)
+            .is_some_and(|rva| code.iter().any(|range| range.contains(&rva)))
+    };
+
+    let mut vtables = BTreeMap::new();
+
+    for offset in (0..image.len()).step_by(8) {
+        let Some(name) = read_u64(offset).and_then(|ptr| locators.get(&ptr)) else {
+            continue;
+        };
+
+        let rva = offset + 8;
+
+        let methods = (rva..)
+            .step_by(8)
+            .map_while(read_u64)
+            .take_while(|ptr| is_method(*ptr))
+            .count();
+
+        if methods == 0 {
+            continue;
+        }
+
+        vtables.entry(name.to_string()).or_insert(VTable {
+            rva: rva as Rva,
+            methods,
+        });
+    }
+
+    vtables
+}
+
+#[cfg(all(test, feature = "scan"))]
+mod tests {
+    use super::*;
+
+    use crate::analysis::offsets::tests::output_dir;
+    use crate::analysis::signatures::tests::build_image;
+
+    #[test]
+    fn rtti() -> Result<()> {
+        const BASE: u64 = 0x180000000;
+
+        let mut code = vec![0u8; 0x80];
+
+        let mut put = |offset: usize, bytes: &[u8]| {
+            code[offset..offset + bytes.len()].copy_from_slice(bytes);
+        };
+
+        // Two methods.
+        put(0x00, &[0xC3, 0xC3]);
+
+        // Type descriptor at 0x1010.
+        put(0x20, b".?AVC_BaseEntity@@\0");
+
+        // Complete object locator at 0x1040.
+        for (offset, value) in [(0x40, 1u32), (0x4C, 0x1010), (0x54, 0x1040)] {
+            put(offset, &value.to_le_bytes());
+        }
+
+        // The vtable, preceded by a pointer to the locator.
+        for (offset, value) in [(0x60, 0x1040u64), (0x68, 0x1000), (0x70, 0x1001)] {
+            put(offset, &(BASE + value).to_le_bytes());
+        }
+
+        let image = build_image(&code);
+
+        let class_names = HashSet::from(["C_BaseEntity", "C_BaseModelEntity"]);
+
+        let vtables = find_vtables(PeView::from_bytes(&image)?, BASE, &class_names);
+
+        assert_eq!(
+            vtables,
+            BTreeMap::from([(
+                "C_BaseEntity".to_string(),
+                VTable {
+                    rva: 0x1068,
+                    methods: 2,
+                },
+            )])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    #[ignore = "needs the game running"]
+    fn base_entity() -> Result<()> {
+        let content = std::fs::read_to_string(output_dir().join("vtables.json"))?;
+        let value: serde_json::Value = serde_json::from_str(&content)?;
+
+        let vtable = &value["client.dll"]["C_BaseEntity"];
+
+        assert!(vtable["rva"].as_u64().is_some_and(|rva| rva != 0));
+        assert!(
+            vtable["methods"]
+                .as_u64()
+                .is_some_and(|methods| methods > 0)
+        );
+
+        Ok(())
+    }
+}
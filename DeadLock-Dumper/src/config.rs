@@ -0,0 +1,380 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, bail};
+
+use log::warn;
+
+use serde::Deserialize;
+
+use crate::analysis::{ExternalPatterns, ReadRetry, ScanOptions};
+use crate::output::{AddressMode, FILE_TYPES};
+
+/// The config file read from the working directory if no other one is given.
+pub const DEFAULT_CONFIG_FILE: &str = "dumper.toml";
+
+/// Everything [`run`](crate::run) needs to know about a dump. The defaults match those of the
+/// command line.
+#[derive(Clone, Debug)]
+pub struct DumperConfig {
+    /// The memflow connector to use, or the native one if `None`.
+    pub connector: Option<String>,
+    pub connector_args: Option<String>,
+    /// Read the process from this memory dump instead of a running game, through `connector`
+    /// (`coredump` by default).
+    pub from_dump: Option<PathBuf>,
+    pub process_name: String,
+    /// Created along with its parents if it doesn't exist.
+    pub output_dir: PathBuf,
+    /// The types of files to generate, any of [`FILE_TYPES`] (`cs`, `dump`, `h`, `hpp`, `json`,
+    /// `md`, `py` and `rs`).
+    pub formats: Vec<String>,
+    pub indent_size: usize,
+    pub address_mode: AddressMode,
+    pub strip_prefix: Option<String>,
+    pub cache_dir: Option<PathBuf>,
+    pub emit_debug: bool,
+    /// Dump even if the build number and the output settings match those of the existing
+    /// `offsets.json`. Dumps to `stdout` are never skipped.
+    pub force: bool,
+    /// Write the offsets to stdout, in the only file type in `formats`, instead of to `output_dir`.
+    pub stdout: bool,
+    pub watch: bool,
+    pub scan: ScanOptions,
+}
+
+impl Default for DumperConfig {
+    fn default() -> Self {
+        Self {
+            connector: None,
+            connector_args: None,
+            from_dump: None,
+            process_name: "project8.exe".to_string(),
+            output_dir: PathBuf::from("output"),
+            formats: ["cs", "hpp", "json", "rs"].map(String::from).to_vec(),
+            indent_size: 4,
+            address_mode: AddressMode::default(),
+            strip_prefix: None,
+            cache_dir: None,
+            emit_debug: false,
+            force: false,
+            stdout: false,
+            watch: false,
+            scan: ScanOptions::default(),
+        }
+    }
+}
+
+impl DumperConfig {
+    pub fn builder() -> DumperConfigBuilder {
+        DumperConfigBuilder::default()
+    }
+}
+
+/// The settings read from a config file, each of which replaces the default of the matching
+/// [`DumperConfig`] field if it's set:
+///
+/// ```toml
+/// formats = ["json", "hpp"]
+/// modules = ["client.dll", "engine2.dll"]
+/// offsets = ["dwEntity*", "dwViewMatrix"]
+/// ```
+///
+/// Unknown keys are ignored with a warning, so a file written for another version still loads.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ConfigFile {
+    pub process_name: Option<String>,
+    pub output_dir: Option<PathBuf>,
+    pub formats: Option<Vec<String>>,
+    pub indent_size: Option<usize>,
+    pub address_mode: Option<AddressMode>,
+    pub strip_prefix: Option<String>,
+    pub modules: Option<Vec<String>>,
+    /// Globs of the offset names to scan for.
+    pub offsets: Option<Vec<String>>,
+    pub strict: Option<bool>,
+    pub count_matches: Option<bool>,
+    pub code_only: Option<bool>,
+    #[serde(flatten)]
+    unknown: BTreeMap<String, toml::Value>,
+}
+
+impl ConfigFile {
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    fn parse(source: &str) -> Result<Self> {
+        let file: Self = toml::from_str(source)?;
+
+        for key in file.unknown.keys() {
+            warn!("ignoring unknown key in config file: {}", key);
+        }
+
+        for format in file.formats.iter().flatten() {
+            if !FILE_TYPES.contains(&format.as_str()) {
+                bail!("unknown file type in config file: {}", format);
+            }
+        }
+
+        Ok(file)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DumperConfigBuilder {
+    config: DumperConfig,
+}
+
+impl DumperConfigBuilder {
+    /// Sets everything `file` specifies. Setters called afterwards take precedence, which is how
+    /// command line options override the file.
+    pub fn config_file(mut self, file: ConfigFile) -> Self {
+        if let Some(process_name) = file.process_name {
+            self = self.process_name(process_name);
+        }
+
+        if let Some(output_dir) = file.output_dir {
+            self = self.output_dir(output_dir);
+        }
+
+        if let Some(formats) = file.formats {
+            self = self.formats(formats);
+        }
+
+        if let Some(indent_size) = file.indent_size {
+            self = self.indent_size(indent_size);
+        }
+
+        if let Some(address_mode) = file.address_mode {
+            self = self.address_mode(address_mode);
+        }
+
+        if let Some(strip_prefix) = file.strip_prefix {
+            self = self.strip_prefix(strip_prefix);
+        }
+
+        if let Some(modules) = file.modules {
+            self = self.modules(modules);
+        }
+
+        if let Some(offsets) = file.offsets {
+            self = self.offsets(offsets);
+        }
+
+        if let Some(strict) = file.strict {
+            self = self.strict(strict);
+        }
+
+        if let Some(count_matches) = file.count_matches {
+            self = self.count_matches(count_matches);
+        }
+
+        if let Some(code_only) = file.code_only {
+            self = self.code_only(code_only);
+        }
+
+        self
+    }
+
+    pub fn connector(mut self, connector: impl Into<Option<String>>) -> Self {
+        self.config.connector = connector.into();
+        self
+    }
+
+    pub fn connector_args(mut self, connector_args: impl Into<Option<String>>) -> Self {
+        self.config.connector_args = connector_args.into();
+        self
+    }
+
+    pub fn from_dump(mut self, from_dump: impl Into<Option<PathBuf>>) -> Self {
+        self.config.from_dump = from_dump.into();
+        self
+    }
+
+    pub fn process_name(mut self, process_name: impl Into<String>) -> Self {
+        self.config.process_name = process_name.into();
+        self
+    }
+
+    pub fn output_dir(mut self, output_dir: impl Into<PathBuf>) -> Self {
+        self.config.output_dir = output_dir.into();
+        self
+    }
+
+    pub fn formats<I, S>(mut self, formats: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.formats = formats.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn indent_size(mut self, indent_size: usize) -> Self {
+        self.config.indent_size = indent_size;
+        self
+    }
+
+    pub fn address_mode(mut self, address_mode: AddressMode) -> Self {
+        self.config.address_mode = address_mode;
+        self
+    }
+
+    pub fn strip_prefix(mut self, strip_prefix: impl Into<Option<String>>) -> Self {
+        self.config.strip_prefix = strip_prefix.into();
+        self
+    }
+
+    pub fn cache_dir(mut self, cache_dir: impl Into<Option<PathBuf>>) -> Self {
+        self.config.cache_dir = cache_dir.into();
+        self
+    }
+
+    /// Also write `patterns_debug.json`, and log the likely location of patterns that fail to
+    /// match.
+    pub fn emit_debug(mut self, emit_debug: bool) -> Self {
+        self.config.emit_debug = emit_debug;
+        self.config.scan.diagnose_stale = emit_debug;
+        self
+    }
+
+    pub fn force(mut self, force: bool) -> Self {
+        self.config.force = force;
+        self
+    }
+
+    pub fn stdout(mut self, stdout: bool) -> Self {
+        self.config.stdout = stdout;
+        self
+    }
+
+    pub fn watch(mut self, watch: bool) -> Self {
+        self.config.watch = watch;
+        self
+    }
+
+    /// Only scan these modules for offsets. All modules are scanned if this is empty.
+    pub fn modules<I, S>(mut self, modules: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.scan.filter.modules = modules.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Only scan for offsets whose names match one of these globs.
+    pub fn offsets<I, S>(mut self, offsets: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.scan.filter.names = offsets.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.config.scan.strict = strict;
+        self
+    }
+
+    pub fn retry(mut self, retry: ReadRetry) -> Self {
+        self.config.scan.retry = retry;
+        self
+    }
+
+    pub fn record_sites(mut self, record_sites: bool) -> Self {
+        self.config.scan.record_sites = record_sites;
+        self
+    }
+
+    pub fn code_only(mut self, code_only: bool) -> Self {
+        self.config.scan.code_only = code_only;
+        self
+    }
+
+    /// The most bytes read from a module at once, or
+    /// [`DEFAULT_CHUNK_SIZE`](crate::analysis::DEFAULT_CHUNK_SIZE) if `None`.
+    pub fn chunk_size(mut self, chunk_size: Option<usize>) -> Self {
+        self.config.scan.chunk_size = chunk_size;
+        self
+    }
+
+    pub fn count_matches(mut self, count_matches: bool) -> Self {
+        self.config.scan.count_matches = count_matches;
+        self
+    }
+
+    /// Also write the first `prologue_len` bytes of each function, as `<name>_prologue`.
+    pub fn prologue_len(mut self, prologue_len: usize) -> Self {
+        self.config.scan.prologue_len = prologue_len;
+        self
+    }
+
+    /// Patterns to scan for in addition to the registered ones, e.g. loaded with
+    /// [`load_patterns`](crate::analysis::load_patterns).
+    pub fn patterns(mut self, patterns: ExternalPatterns) -> Self {
+        self.config.scan.patterns = patterns;
+        self
+    }
+
+    pub fn build(self) -> DumperConfig {
+        self.config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builder() {
+        let config = DumperConfig::builder()
+            .process_name("deadlock.exe")
+            .formats(["json"])
+            .modules(["client.dll"])
+            .strict(true)
+            .build();
+
+        assert_eq!(config.process_name, "deadlock.exe");
+        assert_eq!(config.formats, ["json"]);
+        assert_eq!(config.output_dir, PathBuf::from("output"));
+        assert_eq!(config.scan.filter.modules, ["client.dll"]);
+        assert!(config.scan.strict);
+        assert!(config.connector.is_none());
+    }
+
+    #[test]
+    fn config_file() -> Result<()> {
+        let file = ConfigFile::parse(
+            r#"
+            formats = ["json"]
+            modules = ["client.dll"]
+            address_mode = "absolute"
+            strict = true
+            code_only = true
+            unknown_key = true
+            "#,
+        )?;
+
+        let config = DumperConfig::builder()
+            .config_file(file)
+            .modules(["engine2.dll"])
+            .code_only(false)
+            .build();
+
+        assert_eq!(config.formats, ["json"]);
+        assert_eq!(config.address_mode, AddressMode::Absolute);
+        assert!(config.scan.strict);
+        assert!(!config.scan.code_only);
+        assert!(!config.scan.count_matches);
+        assert_eq!(config.scan.filter.modules, ["engine2.dll"]);
+        assert_eq!(config.process_name, "project8.exe");
+
+        assert!(ConfigFile::parse("formats = [\"xml\"]").is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,307 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Write};
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::analysis::{OffsetMap, RvaMap};
+use crate::output::META_KEY;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct Change {
+    /// The module the value belongs to, followed by the class name for schema fields (e.g.
+    /// `client.dll::C_BaseEntity`).
+    pub scope: String,
+    pub name: String,
+    pub kind: ChangeKind,
+    pub old: Option<u64>,
+    pub new: Option<u64>,
+}
+
+/// Compares two generated JSON files, either `offsets.json` or one of the schema files.
+pub fn diff_files(old: &Path, new: &Path) -> Result<Vec<Change>> {
+    let old: Value = serde_json::from_str(&fs::read_to_string(old)?)?;
+    let new: Value = serde_json::from_str(&fs::read_to_string(new)?)?;
+
+    Ok(diff(&old, &new))
+}
+
+/// Compares a reference `offsets.json`, e.g. a hand-curated one, against freshly scanned offsets.
+/// The reference has to be written as RVAs, and the build number, module location and prologues
+/// stored next to its offsets aren't compared.
+pub fn diff_against(reference: &Path, offsets: &OffsetMap) -> Result<Vec<Change>> {
+    Ok(diff_offsets(
+        &RvaMap::load(reference)?,
+        &RvaMap(offsets.clone()),
+    ))
+}
+
+pub fn diff(old: &Value, new: &Value) -> Vec<Change> {
+    compare(flatten(old), flatten(new))
+}
+
+/// Like [`diff`], but compares offsets that were already parsed.
+pub fn diff_offsets(old: &RvaMap, new: &RvaMap) -> Vec<Change> {
+    let values = |offsets: &RvaMap| -> BTreeMap<(String, String), u64> {
+        offsets
+            .0
+            .iter()
+            .flat_map(|(module_name, offsets)| {
+                offsets
+                    .iter()
+                    .map(move |(name, rva)| ((module_name.clone(), name.clone()), *rva as u64))
+            })
+            .collect()
+    };
+
+    compare(values(old), values(new))
+}
+
+fn compare(
+    old: BTreeMap<(String, String), u64>,
+    new: BTreeMap<(String, String), u64>,
+) -> Vec<Change> {
+    let keys: BTreeSet<_> = old.keys().chain(new.keys()).collect();
+
+    keys.into_iter()
+        .filter_map(|key| {
+            let (old, new) = (old.get(key).copied(), new.get(key).copied());
+
+            let kind = match (old, new) {
+                (None, Some(_)) => ChangeKind::Added,
+                (Some(_), None) => ChangeKind::Removed,
+                (Some(old), Some(new)) if old != new => ChangeKind::Changed,
+                _ => return None,
+            };
+
+            Some(Change {
+                scope: key.0.clone(),
+                name: key.1.clone(),
+                kind,
+                old,
+                new,
+            })
+        })
+        .collect()
+}
+
+/// Writes the changes as a table, grouped by module (and class, for schema fields).
+pub fn write_table<W: Write>(out: &mut W, changes: &[Change]) -> fmt::Result {
+    if changes.is_empty() {
+        return writeln!(out, "no changes");
+    }
+
+    let width = changes
+        .iter()
+        .map(|change| change.name.len())
+        .max()
+        .unwrap_or_default();
+
+    let mut scope = None;
+
+    for change in changes {
+        if scope != Some(&change.scope) {
+            if scope.is_some() {
+                writeln!(out)?;
+            }
+
+            writeln!(out, "{}", change.scope)?;
+
+            scope = Some(&change.scope);
+        }
+
+        let hex = |value: Option<u64>| {
+            value
+                .map(|value| format!("{:#X}", value))
+                .unwrap_or_default()
+        };
+
+        match change.kind {
+            ChangeKind::Added => writeln!(out, "  + {:width$}  {}", change.name, hex(change.new))?,
+            ChangeKind::Removed => {
+                writeln!(out, "  - {:width$}  {}", change.name, hex(change.old))?
+            }
+            ChangeKind::Changed => writeln!(
+                out,
+                "  ~ {:width$}  {} -> {}",
+                change.name,
+                hex(change.old),
+                hex(change.new)
+            )?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Collects every numeric value in the file, keyed by scope and name.
+fn flatten(value: &Value) -> BTreeMap<(String, String), u64> {
+    let mut values = BTreeMap::new();
+
+    let Some(modules) = value.as_object() else {
+        return values;
+    };
+
+    for (module_name, module) in modules {
+        if module_name == META_KEY {
+            continue;
+        }
+
+        if let Some(classes) = module.get("classes").and_then(Value::as_object) {
+            for (class_name, class) in classes {
+                let Some(fields) = class.get("fields").and_then(Value::as_object) else {
+                    continue;
+                };
+
+                // Fields were written as plain offsets before they had their metadata alongside.
+                for (field_name, field) in fields {
+                    if let Some(offset) = field
+                        .get("offset")
+                        .and_then(Value::as_u64)
+                        .or_else(|| field.as_u64())
+                    {
+                        values.insert(
+                            (
+                                format!("{}::{}", module_name, class_name),
+                                field_name.clone(),
+                            ),
+                            offset,
+                        );
+                    }
+                }
+            }
+        } else if let Some(offsets) = module.as_object() {
+            for (name, value) in offsets {
+                if let Some(value) = value.as_u64() {
+                    values.insert((module_name.clone(), name.clone()), value);
+                }
+            }
+        }
+    }
+
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    fn offsets() {
+        let old = json!({
+            "client.dll": { "dwEntityList": 0x100, "dwViewMatrix": 0x200 },
+            "engine2.dll": { "dwBuildNumber": 0x300 },
+        });
+
+        let new = json!({
+            "client.dll": { "dwEntityList": 0x100, "dwViewMatrix": 0x210, "dwPrediction": 0x400 },
+        });
+
+        let changes = diff(&old, &new);
+
+        assert_eq!(changes.len(), 3);
+        assert_eq!(changes[0].name, "dwPrediction");
+        assert_eq!(changes[0].kind, ChangeKind::Added);
+        assert_eq!((changes[1].old, changes[1].new), (Some(0x200), Some(0x210)));
+        assert_eq!(changes[2].scope, "engine2.dll");
+        assert_eq!(changes[2].kind, ChangeKind::Removed);
+    }
+
+    #[test]
+    fn against_reference() -> Result<()> {
+        let temp = tempfile::tempdir()?;
+        let path = temp.path().join("reference.json");
+
+        let reference = json!({
+            "client.dll": { "dwEntityList": 0x100, "dwViewMatrix": 0x200 },
+            "engine2.dll": { "dwBuildNumber": 0x300 },
+            "version": 1,
+            "build_number": 5812,
+        });
+
+        fs::write(&path, reference.to_string())?;
+
+        let offsets = OffsetMap::from([
+            (
+                "client.dll".to_string(),
+                BTreeMap::from([
+                    ("dwEntityList".to_string(), 0x100),
+                    ("dwViewMatrix".to_string(), 0x210),
+                    ("dwPrediction".to_string(), 0x400),
+                ]),
+            ),
+            (
+                "engine2.dll".to_string(),
+                BTreeMap::from([("dwBuildNumber".to_string(), 0x300)]),
+            ),
+        ]);
+
+        let changes = diff_against(&path, &offsets)?;
+
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].name, "dwPrediction");
+        assert_eq!(changes[0].kind, ChangeKind::Added);
+        assert_eq!(changes[1].name, "dwViewMatrix");
+        assert_eq!(changes[1].kind, ChangeKind::Changed);
+
+        Ok(())
+    }
+
+    #[test]
+    fn schemas() {
+        let old = json!({
+            "client.dll": {
+                "classes": { "C_BaseEntity": { "parent": null, "fields": { "m_iHealth": 0x34C } } },
+                "enums": {},
+            },
+        });
+
+        let new = json!({
+            "client.dll": {
+                "classes": {
+                    "C_BaseEntity": {
+                        "parent": null,
+                        "fields": { "m_iHealth": { "offset": 0x354, "metadata": [] } },
+                    },
+                },
+                "enums": {},
+            },
+        });
+
+        let changes = diff(&old, &new);
+
+        assert_eq!(
+            changes,
+            [Change {
+                scope: "client.dll::C_BaseEntity".to_string(),
+                name: "m_iHealth".to_string(),
+                kind: ChangeKind::Changed,
+                old: Some(0x34C),
+                new: Some(0x354),
+            }]
+        );
+
+        let mut table = String::new();
+
+        write_table(&mut table, &changes).unwrap();
+
+        assert_eq!(
+            table,
+            "client.dll::C_BaseEntity\n  ~ m_iHealth  0x34C -> 0x354\n"
+        );
+    }
+}
@@ -0,0 +1,47 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum DumperError {
+    #[error("module not found: {0}")]
+    ModuleNotFound(String),
+
+    #[cfg(feature = "scan")]
+    #[error("unable to parse connector arguments \"{args}\": {source}")]
+    ConnectorArgs {
+        args: String,
+        source: memflow::error::Error,
+    },
+
+    /// Returned where there is no native OS layer to fall back to.
+    #[cfg(feature = "scan")]
+    #[error("no connector specified")]
+    NoConnector,
+
+    #[cfg(feature = "scan")]
+    #[error("failed to read module {module}: {source}")]
+    MemoryRead {
+        module: String,
+        source: memflow::error::Error,
+    },
+
+    #[cfg(feature = "scan")]
+    /// `chunks` holds the offset of each chunk into the module that could only be partly read.
+    #[error(
+        "failed to read the chunk(s) at {} of module {module}",
+        .chunks.iter().map(|offset| format!("{:#X}", offset)).collect::<Vec<_>>().join(", ")
+    )]
+    PartialRead { module: String, chunks: Vec<usize> },
+
+    #[error("failed to parse PE image: {0}")]
+    PeParse(String),
+
+    /// Returned in strict mode once everything else was dumped.
+    #[error("pattern(s) failed to match: {}", .0.join(", "))]
+    StalePatterns(Vec<String>),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
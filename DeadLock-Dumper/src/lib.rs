@@ -0,0 +1,502 @@
+#[cfg(feature = "scan")]
+use std::collections::{BTreeMap, BTreeSet};
+#[cfg(feature = "scan")]
+use std::fs;
+use std::io;
+use std::path::Path;
+#[cfg(feature = "scan")]
+use std::str::FromStr;
+#[cfg(feature = "scan")]
+use std::thread;
+#[cfg(feature = "scan")]
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+#[cfg(feature = "scan")]
+use anyhow::{anyhow, bail};
+
+#[cfg(feature = "scan")]
+use clap::ValueEnum;
+
+#[cfg(feature = "scan")]
+use log::{debug, error, info};
+
+#[cfg(feature = "scan")]
+use memflow::prelude::v1::*;
+
+#[cfg(feature = "scan")]
+use pelite::pe64::Rva;
+
+#[cfg(feature = "codegen")]
+pub use config::{ConfigFile, DEFAULT_CONFIG_FILE, DumperConfig, DumperConfigBuilder};
+
+#[cfg(feature = "codegen")]
+use analysis::AnalysisResult;
+#[cfg(feature = "scan")]
+use analysis::{OffsetFilter, OffsetMap, Progress, ScanOptions};
+#[cfg(feature = "scan")]
+use error::DumperError;
+#[cfg(feature = "codegen")]
+use output::Output;
+
+pub mod analysis;
+#[cfg(feature = "codegen")]
+pub mod diff;
+pub mod error;
+#[cfg(feature = "scan")]
+pub mod memory;
+#[cfg(feature = "codegen")]
+pub mod output;
+#[cfg(feature = "scan")]
+pub mod reader;
+#[cfg(feature = "scan")]
+pub mod source2;
+
+#[cfg(feature = "codegen")]
+mod config;
+
+#[cfg(feature = "scan")]
+/// The connector used to read memory dumps if no other one is specified.
+const DUMP_CONNECTOR: &str = "coredump";
+
+#[cfg(feature = "scan")]
+/// The delay between checks for a running game that was already dumped.
+const WATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+#[cfg(feature = "scan")]
+/// The longest delay between checks for the game process while it isn't running.
+const WATCH_MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Attaches to the game and writes the generated files, either once or, if
+/// [`DumperConfig::watch`] is set, whenever the game is (re)started or updated.
+#[cfg(feature = "scan")]
+pub fn run(config: DumperConfig) -> Result<()> {
+    run_with_progress(config, &())
+}
+
+/// Like [`run`], but reports the progress of scanning for offsets to `progress`.
+#[cfg(feature = "scan")]
+pub fn run_with_progress(config: DumperConfig, progress: &dyn Progress) -> Result<()> {
+    validate(&config)?;
+
+    let mut os = create_os(&config)?;
+
+    run_with_os(&mut os, &config, progress)
+}
+
+/// Like [`run_with_progress`], but finds the game through an OS the caller created, e.g. one that
+/// a long-running service keeps open between dumps. [`DumperConfig::connector`] and
+/// [`DumperConfig::from_dump`] are ignored, as they only affect how the OS is created.
+#[cfg(feature = "scan")]
+pub fn run_with_os<O: Os>(
+    os: &mut O,
+    config: &DumperConfig,
+    progress: &dyn Progress,
+) -> Result<()> {
+    validate(config)?;
+
+    if config.watch {
+        return watch(os, config, progress);
+    }
+
+    let mut process = os.process_by_name(&config.process_name)?;
+
+    let dumped = dump(&mut process, config, progress)?;
+
+    // Checked only after dumping, so the offsets that did match are still written.
+    if config.scan.strict && !dumped.missing_offsets.is_empty() {
+        return Err(
+            DumperError::StalePatterns(dumped.missing_offsets.into_iter().collect()).into(),
+        );
+    }
+
+    Ok(())
+}
+
+/// Scans the game for offsets without writing any files, returning them keyed by module name.
+#[cfg(feature = "scan")]
+pub fn scan_offsets(config: &DumperConfig) -> Result<OffsetMap> {
+    let mut os = create_os(config)?;
+    let mut process = os.process_by_name(&config.process_name)?;
+
+    Ok(analysis::offsets(&mut process, &config.scan, &())?.offsets)
+}
+
+/// A single offset found by [`lookup_offset`].
+#[cfg(feature = "scan")]
+#[derive(Clone, Debug)]
+pub struct OffsetLookup {
+    pub module_name: String,
+    pub rva: Rva,
+    /// The address of the offset in the game, if the module's base is known.
+    pub address: Option<umem>,
+}
+
+/// Scans the game for the offset `name` alone, only reading the module its pattern is registered
+/// in (or loaded for, see [`ScanOptions::patterns`]). Returns `None` if the pattern doesn't match.
+#[cfg(feature = "scan")]
+pub fn lookup_offset(config: &DumperConfig, name: &str) -> Result<Option<OffsetLookup>> {
+    let module_name = config
+        .scan
+        .patterns
+        .iter()
+        .find(|(_, patterns)| patterns.contains_key(name))
+        .map(|(module_name, _)| module_name.clone())
+        .or_else(|| {
+            analysis::pattern_sources()
+                .get(name)
+                .map(|(module_name, _)| module_name.to_string())
+        })
+        .ok_or_else(|| anyhow!("no pattern is registered for {}", name))?;
+
+    let options = ScanOptions {
+        filter: OffsetFilter {
+            modules: vec![module_name.clone()],
+            names: vec![name.to_string()],
+        },
+        ..config.scan.clone()
+    };
+
+    let mut os = create_os(config)?;
+    let mut process = os.process_by_name(&config.process_name)?;
+
+    let scan = analysis::offsets(&mut process, &options, &())?;
+
+    let Some(rva) = scan
+        .offsets
+        .get(&module_name)
+        .and_then(|offsets| offsets.get(name))
+        .copied()
+    else {
+        return Ok(None);
+    };
+
+    let address = scan
+        .modules
+        .get(&module_name)
+        .and_then(|module| module.base)
+        .map(|base| base + rva as umem);
+
+    Ok(Some(OffsetLookup {
+        module_name,
+        rva,
+        address,
+    }))
+}
+
+/// Writes the offset files generated from the `offsets.json` of a previous dump, which has to be
+/// written as RVAs, without reading the game. Along with [`diff`], this is all that's available with
+/// only the `codegen` feature. Only the output options of `config` are used.
+#[cfg(feature = "codegen")]
+pub fn generate_offsets(config: &DumperConfig, offsets_json: &Path) -> Result<()> {
+    let (offsets, modules, build_number) = analysis::load_offsets(offsets_json)?;
+
+    let result = AnalysisResult {
+        build_number,
+        modules,
+        offsets,
+        ..Default::default()
+    };
+
+    let output = Output::new(
+        &config.formats,
+        config.indent_size,
+        &config.output_dir,
+        config.address_mode,
+        &result,
+        config.strip_prefix.as_deref(),
+    )?;
+
+    if config.stdout {
+        output.write_offsets(&mut io::stdout().lock())
+    } else {
+        output.dump_offsets()
+    }
+}
+
+/// Like [`scan_offsets`], but runs the scan on Tokio's blocking thread pool so that memflow's
+/// blocking reads don't stall the runtime.
+#[cfg(feature = "async")]
+pub async fn scan_offsets_async(config: DumperConfig) -> Result<OffsetMap> {
+    tokio::task::spawn_blocking(move || scan_offsets(&config)).await?
+}
+
+#[cfg(feature = "scan")]
+fn validate(config: &DumperConfig) -> Result<()> {
+    if config.stdout && config.formats.len() != 1 {
+        bail!(
+            "only one file type can be written to stdout, got {}",
+            config.formats.join(", ")
+        );
+    }
+
+    if config.watch && config.from_dump.is_some() {
+        bail!("a memory dump can't be watched");
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "scan")]
+fn create_os(config: &DumperConfig) -> Result<OsInstanceArcBox<'static>> {
+    let mut conn_args = config
+        .connector_args
+        .as_deref()
+        .map(|args| {
+            ConnectorArgs::from_str(args).map_err(|source| DumperError::ConnectorArgs {
+                args: args.to_string(),
+                source,
+            })
+        })
+        .transpose()?
+        .unwrap_or_default();
+
+    // Memory dumps are read through a file connector, with the dump as its target.
+    let connector = match &config.from_dump {
+        Some(path) => {
+            conn_args.target = Some(path.to_string_lossy().as_ref().into());
+
+            Some(config.connector.as_deref().unwrap_or(DUMP_CONNECTOR))
+        }
+        None => config.connector.as_deref(),
+    };
+
+    let os = match connector {
+        Some(conn) => {
+            let mut inventory = Inventory::scan();
+
+            inventory
+                .builder()
+                .connector(conn)
+                .args(conn_args)
+                .os("win32")
+                .build()?
+        }
+        None => {
+            #[cfg(windows)]
+            {
+                memflow_native::create_os(&OsArgs::default(), LibArc::default())?
+            }
+            #[cfg(not(windows))]
+            {
+                return Err(DumperError::NoConnector.into());
+            }
+        }
+    };
+
+    Ok(os)
+}
+
+#[cfg(feature = "scan")]
+/// What [`dump`] read from the game.
+struct Dumped {
+    build_number: Option<u32>,
+    /// Offsets that can be used to read the build number again.
+    offsets: OffsetMap,
+    /// Offsets whose patterns didn't match, which is empty if nothing was dumped.
+    missing_offsets: BTreeSet<String>,
+}
+
+/// Dumps the game. Unless [`DumperConfig::force`] is set, nothing is dumped if the build number
+/// and the settings match those of the existing `offsets.json`. Dumps that also write debug output
+/// or a module cache are never skipped, as those aren't part of `offsets.json`.
+#[cfg(feature = "scan")]
+fn dump<P: Process + MemoryView>(
+    process: &mut P,
+    config: &DumperConfig,
+    progress: &dyn Progress,
+) -> Result<Dumped> {
+    let settings = output_settings(config);
+
+    // Nothing would be printed if the dump was skipped, so writing to stdout always dumps.
+    let skippable = !config.stdout && !config.emit_debug && config.cache_dir.is_none();
+
+    if !config.force && skippable {
+        match current_build_number(process) {
+            Ok((build_number, offsets)) => {
+                if let Some(missing_offsets) =
+                    previous_dump(&config.output_dir, build_number, &settings)
+                {
+                    info!(
+                        "build {} was already dumped with the same settings, skipping",
+                        build_number
+                    );
+
+                    return Ok(Dumped {
+                        build_number: Some(build_number),
+                        offsets,
+                        missing_offsets,
+                    });
+                }
+            }
+            Err(err) => debug!("failed to read the build number up front: {}", err),
+        }
+    }
+
+    let now = Instant::now();
+
+    // The images are only kept to be cached, as they're as large as the modules themselves.
+    let options = ScanOptions {
+        keep_images: config.cache_dir.is_some(),
+        ..config.scan.clone()
+    };
+
+    let result = analysis::analyze_all(process, &options, progress)?;
+
+    if let Some(cache_dir) = &config.cache_dir {
+        analysis::dump_modules_to_disk(cache_dir, &result.images, &result.modules)?;
+    }
+
+    let output = Output::new(
+        &config.formats,
+        config.indent_size,
+        &config.output_dir,
+        config.address_mode,
+        &result,
+        config.strip_prefix.as_deref(),
+    )?
+    .with_settings(settings);
+
+    if config.stdout {
+        output.write_offsets(&mut io::stdout().lock())?;
+    } else {
+        output.dump_all()?;
+    }
+
+    if config.emit_debug {
+        output.dump_patterns_debug()?;
+    }
+
+    info!("analysis completed in {:.2?}", now.elapsed());
+
+    Ok(Dumped {
+        build_number: result.build_number,
+        offsets: result.offsets,
+        missing_offsets: result.missing_offsets,
+    })
+}
+
+/// Reads the build number by scanning for just `dwBuildNumber`, which is a lot cheaper than
+/// scanning every module.
+#[cfg(feature = "scan")]
+fn current_build_number<P: Process + MemoryView>(process: &mut P) -> Result<(u32, OffsetMap)> {
+    let options = ScanOptions {
+        filter: OffsetFilter {
+            modules: vec!["engine2.dll".to_string()],
+            names: vec!["dwBuildNumber".to_string()],
+        },
+        ..Default::default()
+    };
+
+    let scan = analysis::offsets(process, &options, &())?;
+
+    let build_number = analysis::build_number(process, &scan.offsets)?;
+
+    Ok((build_number, scan.offsets))
+}
+
+/// The parts of `config` that change what's written for a build, which are stored along with its
+/// build number so that a dump with different ones isn't skipped.
+#[cfg(feature = "scan")]
+fn output_settings(config: &DumperConfig) -> serde_json::Value {
+    let address_mode = config
+        .address_mode
+        .to_possible_value()
+        .map(|value| value.get_name().to_string());
+
+    // Patterns aren't serializable, so they're compared by how they're printed.
+    let patterns: BTreeMap<_, BTreeMap<_, _>> = config
+        .scan
+        .patterns
+        .iter()
+        .map(|(module_name, patterns)| {
+            let patterns = patterns
+                .iter()
+                .map(|(name, pat)| (name, format!("{:?}", pat)))
+                .collect();
+
+            (module_name, patterns)
+        })
+        .collect();
+
+    serde_json::json!({
+        "formats": config.formats,
+        "indent_size": config.indent_size,
+        "address_mode": address_mode,
+        "strip_prefix": config.strip_prefix,
+        "modules": config.scan.filter.modules,
+        "names": config.scan.filter.names,
+        "record_sites": config.scan.record_sites,
+        "prologue_len": config.scan.prologue_len,
+        "patterns": patterns,
+        "code_only": config.scan.code_only,
+        "count_matches": config.scan.count_matches,
+        "strict": config.scan.strict,
+    })
+}
+
+/// The offsets that weren't found by the dump in `output_dir`, if its `offsets.json` was written
+/// for `build_number` with `settings`.
+#[cfg(feature = "scan")]
+fn previous_dump(
+    output_dir: &Path,
+    build_number: u32,
+    settings: &serde_json::Value,
+) -> Option<BTreeSet<String>> {
+    let mut offsets = fs::read_to_string(output_dir.join("offsets.json"))
+        .ok()
+        .and_then(|content| serde_json::from_str::<serde_json::Value>(&content).ok())?;
+
+    let meta = offsets.get_mut(output::META_KEY)?.take();
+
+    if offsets["build_number"] != build_number || meta["settings"] != *settings {
+        return None;
+    }
+
+    serde_json::from_value(meta["missing_offsets"].clone()).ok()
+}
+
+/// Dumps every time the game is started, and again if its build number changes while it's running.
+#[cfg(feature = "scan")]
+fn watch<O: Os>(os: &mut O, config: &DumperConfig, progress: &dyn Progress) -> Result<()> {
+    let mut backoff = WATCH_INTERVAL;
+
+    // The process that was dumped last, along with its build number and the offsets used to read
+    // it again.
+    let mut last_dump: Option<(Pid, Option<u32>, OffsetMap)> = None;
+
+    loop {
+        let Ok(mut process) = os.process_by_name(&config.process_name) else {
+            if last_dump.take().is_some() {
+                info!("{} exited, waiting for it to start", config.process_name);
+            }
+
+            thread::sleep(backoff);
+
+            backoff = (backoff * 2).min(WATCH_MAX_BACKOFF);
+
+            continue;
+        };
+
+        backoff = WATCH_INTERVAL;
+
+        let pid = process.info().pid;
+
+        let outdated = match &last_dump {
+            Some((last_pid, build_number, offsets)) => {
+                *last_pid != pid
+                    || analysis::build_number(&mut process, offsets).ok() != *build_number
+            }
+            None => true,
+        };
+
+        if outdated {
+            match dump(&mut process, config, progress) {
+                Ok(dumped) => last_dump = Some((pid, dumped.build_number, dumped.offsets)),
+                Err(err) => error!("failed to dump {}: {}", config.process_name, err),
+            }
+        }
+
+        thread::sleep(WATCH_INTERVAL);
+    }
+}
@@ -1,32 +1,30 @@
-#![feature(once_cell_try)]
-#![allow(dead_code)]
-#![allow(unused_imports)]
-
+use std::collections::BTreeMap;
 use std::fs::File;
-use std::path::PathBuf;
-use std::str::FromStr;
-use std::time::Instant;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::OnceLock;
+use std::time::Duration;
 
 use anyhow::Result;
 
-use clap::{ArgAction, Parser};
-
-use log::{LevelFilter, info};
+use clap::parser::ValueSource;
+use clap::{ArgAction, CommandFactory, FromArgMatches, Parser, Subcommand};
 
-use memflow::prelude::v1::*;
+use log::LevelFilter;
 
 use simplelog::*;
 
-use output::Output;
-
-mod analysis;
-mod memory;
-mod output;
-mod source2;
+use deadlock_dumper::analysis::{self, ReadRetry};
+use deadlock_dumper::error::DumperError;
+use deadlock_dumper::output::{AddressMode, FILE_TYPES};
+use deadlock_dumper::{ConfigFile, DEFAULT_CONFIG_FILE, DumperConfig, diff};
 
 #[derive(Debug, Parser)]
 #[command(author, version)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// The name of the memflow connector to use.
     #[arg(short, long)]
     connector: Option<String>,
@@ -35,46 +33,216 @@ struct Args {
     #[arg(short = 'a', long)]
     connector_args: Option<String>,
 
-    /// The types of files to generate.
-    #[arg(short, long, value_delimiter = ',', default_values = ["cs", "hpp", "json", "rs"])]
+    /// Read the process name, output directory, file types, indentation, address mode, prefix to
+    /// strip, modules and offsets from this TOML file. Defaults to `dumper.toml` in the working
+    /// directory if it exists. Options passed on the command line take precedence.
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Read the game from a memory dump (e.g. a `.dmp` file) instead of the running process. Uses
+    /// the `coredump` connector unless another one is specified.
+    #[arg(long, conflicts_with = "watch")]
+    from_dump: Option<PathBuf>,
+
+    /// Write the raw images of the scanned modules to this directory for offline re-scanning.
+    #[arg(long)]
+    cache_dir: Option<PathBuf>,
+
+    /// Only read the executable sections of each module when scanning for offsets, which is
+    /// faster over slow connectors.
+    #[arg(long)]
+    code_only: bool,
+
+    /// The most bytes read from a module at once, for connectors that cap the size of a single
+    /// read. Defaults to 4 MiB.
+    #[arg(long)]
+    chunk_size: Option<usize>,
+
+    /// Count how often each pattern matches and warn about ambiguous ones.
+    #[arg(long)]
+    count_matches: bool,
+
+    /// Also write `patterns_debug.json`, listing every pattern and whether it matched, and log the
+    /// bytes around the likely location of patterns that don't match anymore.
+    #[arg(long)]
+    emit_debug: bool,
+
+    /// Dump even if the game's build number and the output settings match those of the existing
+    /// `offsets.json`. Dumps to stdout are never skipped.
+    #[arg(long)]
+    force: bool,
+
+    /// The types of files to generate. `py` writes an IDAPython script that names the offsets, `md`
+    /// a Markdown table of them, `h` a C header indexing them by stable numeric IDs, and `dump`
+    /// writes everything into a single `dump.json`.
+    #[arg(
+        short,
+        long,
+        alias = "output-format",
+        value_delimiter = ',',
+        value_parser = clap::builder::PossibleValuesParser::new(FILE_TYPES.iter().copied()),
+        default_values = ["cs", "hpp", "json", "rs"]
+    )]
     file_types: Vec<String>,
 
+    /// Whether offsets are written relative to the module base or as absolute addresses.
+    #[arg(long, value_enum, default_value_t = AddressMode::Rva)]
+    address_mode: AddressMode,
+
+    /// Only scan these modules for offsets (e.g. `client.dll,engine2.dll`).
+    #[arg(long, value_delimiter = ',')]
+    modules: Vec<String>,
+
+    /// Only scan for offsets whose names match these globs (e.g. `dwEntity*,dwViewMatrix`).
+    #[arg(long = "offsets", value_delimiter = ',')]
+    offset_names: Vec<String>,
+
+    /// Load additional patterns from this TOML file, overriding the built-in ones with the same
+    /// name. Each table is named after a module and maps offset names to patterns.
+    #[arg(long)]
+    patterns: Option<PathBuf>,
+
+    /// Also write the RVA of the instruction each offset was found at, as `<name>_site`.
+    #[arg(long)]
+    record_sites: bool,
+
+    /// Also write this many bytes of the code each function (`fn*`) offset points to, as a hex
+    /// string named `<name>_prologue` in the JSON offsets, e.g. to build hook trampolines from.
+    #[arg(long, default_value_t = 0)]
+    prologue_len: usize,
+
+    /// Fail if one of the modules can't be read, instead of dumping the others. Also exits with
+    /// code 2 after dumping if any pattern failed to match.
+    #[arg(long)]
+    strict: bool,
+
+    /// How many times to try reading a module before giving up.
+    #[arg(long, default_value_t = 3)]
+    read_attempts: u32,
+
+    /// The delay before retrying a failed module read, doubled after every attempt.
+    #[arg(long, default_value_t = 100)]
+    read_delay_ms: u64,
+
+    /// Strip this prefix (e.g. `dw`) from offset names in the generated files.
+    #[arg(long)]
+    strip_prefix: Option<String>,
+
     /// The number of spaces to use per indentation level.
     #[arg(short, long, default_value_t = 4)]
     indent_size: usize,
 
-    /// The output directory to write the generated files to.
-    #[arg(short, long, default_value = "output")]
-    output: PathBuf,
+    /// The directory to write the generated files to, which is created along with its parents if
+    /// it doesn't exist.
+    #[arg(short, long, alias = "output", default_value = "output")]
+    output_dir: PathBuf,
 
     /// The name of the game process.
-    #[arg(short, long, default_value = "deadlock.exe")]
+    #[arg(short, long, default_value = "project8.exe")]
     process_name: String,
 
-    /// Increase logging verbosity. Can be specified multiple times.
+    /// Increase logging verbosity, from `info` to `debug` and then `trace`. Can be specified
+    /// multiple times.
     #[arg(short, long, action = ArgAction::Count)]
     verbose: u8,
 
+    /// Decrease logging verbosity, from `info` to `warn`, `error` and then `off`. Can be specified
+    /// multiple times.
+    #[arg(short, long, action = ArgAction::Count, conflicts_with = "verbose")]
+    quiet: u8,
+
+    /// Set the logging verbosity directly (`off`, `error`, `warn`, `info`, `debug` or `trace`).
+    #[arg(long, conflicts_with_all = ["verbose", "quiet"])]
+    log_level: Option<LevelFilter>,
+
     /// Prevent creation of the deadlock-dumper.log file.
     #[arg(short, long)]
     no_log_file: bool,
+
+    /// Write the offsets to stdout instead of the output directory. Only one file type can be
+    /// requested with `-f`, and logs are written to stderr.
+    #[arg(long, conflicts_with = "watch")]
+    stdout: bool,
+
+    /// Keep running and dump again whenever the game is (re)started or its build number changes.
+    #[arg(long)]
+    watch: bool,
+
+    /// Only scan for this offset, in the module its pattern is registered in, and print its RVA
+    /// and address instead of writing any files. Exits with code 2 if it doesn't match.
+    #[arg(long, conflicts_with_all = ["watch", "stdout", "modules", "offset_names"])]
+    only: Option<String>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Compare two previously generated JSON files (offsets or schemas) and print what changed.
+    Diff {
+        old: PathBuf,
+        new: PathBuf,
+
+        /// Print the changes as JSON instead of a table.
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// List the modules and offsets that are scanned for, along with their patterns if `-v` is
+    /// passed.
+    List,
+
+    /// Scan the game and compare the offsets against a reference `offsets.json`, printing every
+    /// offset that differs or only exists on one side. Exits with code 1 if there are any.
+    Verify {
+        #[arg(long)]
+        against: PathBuf,
+
+        /// Print the differences as JSON instead of a table.
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+/// The level logged to the terminal, which is `info` unless changed with `-v`, `-q` or
+/// `--log-level`.
+fn level_filter(args: &Args) -> LevelFilter {
+    const LEVELS: [LevelFilter; 6] = [
+        LevelFilter::Off,
+        LevelFilter::Error,
+        LevelFilter::Warn,
+        LevelFilter::Info,
+        LevelFilter::Debug,
+        LevelFilter::Trace,
+    ];
+
+    if let Some(level) = args.log_level {
+        return level;
+    }
+
+    let index = (3 + args.verbose as usize).saturating_sub(args.quiet as usize);
+
+    LEVELS[index.min(LEVELS.len() - 1)]
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
+/// Sets up the logger before anything is dumped. Only the first call has an effect, so it's safe
+/// to call more than once.
+fn init_logger(args: &Args) -> Result<()> {
+    static LOGGER: OnceLock<()> = OnceLock::new();
 
-    let level_filter = match args.verbose {
-        0 => LevelFilter::Error,
-        1 => LevelFilter::Warn,
-        2 => LevelFilter::Info,
-        3 => LevelFilter::Debug,
-        _ => LevelFilter::Trace,
+    if LOGGER.get().is_some() {
+        return Ok(());
+    }
+
+    // Keep stdout clean when the output is written to it.
+    let terminal_mode = if args.stdout {
+        TerminalMode::Stderr
+    } else {
+        TerminalMode::Mixed
     };
 
     let mut loggers: Vec<Box<dyn SharedLogger>> = vec![TermLogger::new(
-        level_filter,
+        level_filter(args),
         Config::default(),
-        TerminalMode::Mixed,
+        terminal_mode,
         ColorChoice::Auto,
     )];
 
@@ -89,44 +257,197 @@ fn main() -> Result<()> {
 
     CombinedLogger::init(loggers)?;
 
-    let conn_args = args
-        .connector_args
-        .map(|s| ConnectorArgs::from_str(&s).expect("unable to parse connector arguments"))
-        .unwrap_or_default();
-
-    let mut os = match args.connector {
-        Some(conn) => {
-            let mut inventory = Inventory::scan();
-
-            inventory
-                .builder()
-                .connector(&conn)
-                .args(conn_args)
-                .os("win32")
-                .build()?
+    LOGGER.get_or_init(|| ());
+
+    Ok(())
+}
+
+/// The exit code if any pattern failed to match, in strict mode or with `--only`.
+const STALE_EXIT_CODE: u8 = 2;
+
+/// The exit code of `verify` if the scanned offsets differ from the reference.
+const MISMATCH_EXIT_CODE: u8 = 1;
+
+fn print_changes(changes: &[diff::Change], json: bool) -> Result<()> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(changes)?);
+    } else {
+        let mut table = String::new();
+
+        diff::write_table(&mut table, changes)?;
+
+        print!("{}", table);
+    }
+
+    Ok(())
+}
+
+/// Loads the config file passed with `--config`, or the default one if it exists.
+fn load_config_file(args: &Args) -> Result<ConfigFile> {
+    match &args.config {
+        Some(path) => ConfigFile::load(path),
+        None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+            ConfigFile::load(Path::new(DEFAULT_CONFIG_FILE))
         }
-        None => {
-            #[cfg(windows)]
-            {
-                memflow_native::create_os(&OsArgs::default(), LibArc::default())?
+        None => Ok(ConfigFile::default()),
+    }
+}
+
+fn main() -> Result<ExitCode> {
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+
+    // Whether an option was passed on the command line, in which case it overrides the config file.
+    let from_cli = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+
+    init_logger(&args)?;
+
+    match &args.command {
+        Some(Command::Diff { old, new, json }) => {
+            print_changes(&diff::diff_files(old, new)?, *json)?;
+
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Command::List) => {
+            let mut modules = BTreeMap::<_, Vec<_>>::new();
+
+            for (name, (module_name, patterns)) in analysis::pattern_sources() {
+                modules
+                    .entry(module_name)
+                    .or_default()
+                    .push((name, patterns));
             }
-            #[cfg(not(windows))]
-            {
-                panic!("no connector specified")
+
+            for (module_name, offsets) in modules {
+                println!("{}", module_name);
+
+                for (name, patterns) in offsets {
+                    println!("  {}", name);
+
+                    if args.verbose > 0 {
+                        for pattern in patterns {
+                            println!("    {}", pattern);
+                        }
+                    }
+                }
             }
+
+            return Ok(ExitCode::SUCCESS);
         }
+        Some(Command::Verify { .. }) | None => {}
+    }
+
+    let patterns = match &args.patterns {
+        Some(path) => analysis::load_patterns(path)?,
+        None => Default::default(),
     };
 
-    let mut process = os.process_by_name(&args.process_name)?;
+    let mut builder = DumperConfig::builder()
+        .config_file(load_config_file(&args)?)
+        .connector(args.connector)
+        .connector_args(args.connector_args)
+        .from_dump(args.from_dump)
+        .cache_dir(args.cache_dir)
+        .emit_debug(args.emit_debug)
+        .force(args.force)
+        .stdout(args.stdout)
+        .watch(args.watch)
+        .retry(ReadRetry {
+            attempts: args.read_attempts,
+            base_delay: Duration::from_millis(args.read_delay_ms),
+        })
+        .chunk_size(args.chunk_size)
+        .record_sites(args.record_sites)
+        .prologue_len(args.prologue_len)
+        .patterns(patterns);
 
-    let now = Instant::now();
+    if from_cli("process_name") {
+        builder = builder.process_name(args.process_name);
+    }
 
-    let result = analysis::analyze_all(&mut process)?;
-    let output = Output::new(&args.file_types, args.indent_size, &args.output, &result)?;
+    if from_cli("output_dir") {
+        builder = builder.output_dir(args.output_dir);
+    }
 
-    output.dump_all(&mut process)?;
+    if from_cli("file_types") {
+        builder = builder.formats(args.file_types);
+    }
 
-    info!("analysis completed in {:.2?}", now.elapsed());
+    if from_cli("indent_size") {
+        builder = builder.indent_size(args.indent_size);
+    }
 
-    Ok(())
+    if from_cli("address_mode") {
+        builder = builder.address_mode(args.address_mode);
+    }
+
+    if from_cli("strip_prefix") {
+        builder = builder.strip_prefix(args.strip_prefix);
+    }
+
+    if from_cli("modules") {
+        builder = builder.modules(args.modules);
+    }
+
+    if from_cli("offset_names") {
+        builder = builder.offsets(args.offset_names);
+    }
+
+    if from_cli("strict") {
+        builder = builder.strict(args.strict);
+    }
+
+    if from_cli("count_matches") {
+        builder = builder.count_matches(args.count_matches);
+    }
+
+    if from_cli("code_only") {
+        builder = builder.code_only(args.code_only);
+    }
+
+    let config = builder.build();
+
+    if let Some(name) = &args.only {
+        let Some(found) = deadlock_dumper::lookup_offset(&config, name)? else {
+            eprintln!("pattern failed to match: {}", name);
+
+            return Ok(ExitCode::from(STALE_EXIT_CODE));
+        };
+
+        match found.address {
+            Some(address) => println!(
+                "{} {}: {:#X} ({:#X})",
+                found.module_name, name, found.rva, address
+            ),
+            None => println!("{} {}: {:#X}", found.module_name, name, found.rva),
+        }
+
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if let Some(Command::Verify { against, json }) = &args.command {
+        let changes = diff::diff_against(against, &deadlock_dumper::scan_offsets(&config)?)?;
+
+        print_changes(&changes, *json)?;
+
+        return Ok(if changes.is_empty() {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::from(MISMATCH_EXIT_CODE)
+        });
+    }
+
+    match deadlock_dumper::run(config) {
+        Ok(()) => Ok(ExitCode::SUCCESS),
+        Err(err) => match err.downcast_ref::<DumperError>() {
+            Some(DumperError::StalePatterns(names)) => {
+                for name in names {
+                    eprintln!("pattern failed to match: {}", name);
+                }
+
+                Ok(ExitCode::from(STALE_EXIT_CODE))
+            }
+            _ => Err(err),
+        },
+    }
 }
@@ -0,0 +1,55 @@
+use memflow::prelude::*;
+
+/// Reads a schema name. Source 2 stores them as UTF-8, but a few are wide strings, which
+/// `read_utf8_lossy` would cut off after their first character. Those are told apart by the NUL
+/// byte following each of their first two ASCII characters, which a UTF-8 name never has.
+pub fn read_name(mem: &mut impl MemoryView, addr: Address, max_len: usize) -> Result<String> {
+    let mut buf = vec![0; max_len * 2];
+
+    mem.read_raw_into(addr, &mut buf).data_part()?;
+
+    if is_wide(&buf) {
+        return Ok(decode_wide(&buf));
+    }
+
+    let len = buf[..max_len]
+        .iter()
+        .position(|byte| *byte == 0)
+        .unwrap_or(max_len);
+
+    Ok(String::from_utf8_lossy(&buf[..len]).into_owned())
+}
+
+fn is_wide(bytes: &[u8]) -> bool {
+    matches!(bytes, [a, 0, b, 0, ..] if a.is_ascii_graphic() && b.is_ascii_graphic())
+}
+
+fn decode_wide(bytes: &[u8]) -> String {
+    let units: Vec<_> = bytes
+        .chunks_exact(2)
+        .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+        .take_while(|unit| *unit != 0)
+        .collect();
+
+    String::from_utf16_lossy(&units)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodings() {
+        let wide: Vec<u8> = "m_iHealth\0"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+
+        assert!(is_wide(&wide));
+        assert_eq!(decode_wide(&wide), "m_iHealth");
+
+        assert!(!is_wide(b"m_iHealth\0"));
+        assert!(!is_wide(b"x\0"));
+        assert!(!is_wide(&[]));
+    }
+}
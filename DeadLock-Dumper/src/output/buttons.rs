@@ -1,7 +1,7 @@
 use std::collections::BTreeMap;
 use std::fmt::{self, Write};
 
-use super::{ButtonMap, CodeWriter, Formatter};
+use super::{ButtonMap, CodeWriter, Formatter, cs_ident, rs_ident};
 
 impl CodeWriter for ButtonMap {
     fn write_cs(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
@@ -10,7 +10,7 @@ impl CodeWriter for ButtonMap {
 
             fmt.block("public static class Buttons", false, |fmt| {
                 for (name, value) in self {
-                    writeln!(fmt, "public const nint {} = {:#X};", name, value)?;
+                    writeln!(fmt, "public const nint {} = {:#X};", cs_ident(name), value)?;
                 }
 
                 Ok(())
@@ -54,13 +54,7 @@ impl CodeWriter for ButtonMap {
 
             fmt.block("pub mod buttons", false, |fmt| {
                 for (name, value) in self {
-                    let mut name = name.clone();
-
-                    if name == "use" {
-                        name = format!("r#{}", name);
-                    }
-
-                    writeln!(fmt, "pub const {}: usize = {:#X};", name, value)?;
+                    writeln!(fmt, "pub const {}: usize = {:#X};", rs_ident(name), value)?;
                 }
 
                 Ok(())
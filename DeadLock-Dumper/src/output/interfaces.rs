@@ -3,7 +3,7 @@ use std::fmt::{self, Write};
 
 use heck::{AsPascalCase, AsSnakeCase};
 
-use super::{CodeWriter, Formatter, InterfaceMap, slugify};
+use super::{CodeWriter, Formatter, InterfaceMap, cs_ident, rs_ident, slugify};
 
 impl CodeWriter for InterfaceMap {
     fn write_cs(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
@@ -20,10 +20,16 @@ impl CodeWriter for InterfaceMap {
                                 writeln!(
                                     fmt,
                                     "public static readonly nint {} = unchecked((nint){:#X});",
-                                    name, value
+                                    cs_ident(name),
+                                    value
                                 )?;
                             } else {
-                                writeln!(fmt, "public const nint {} = {:#X};", name, value)?;
+                                writeln!(
+                                    fmt,
+                                    "public const nint {} = {:#X};",
+                                    cs_ident(name),
+                                    value
+                                )?;
                             };
                         }
 
@@ -91,7 +97,12 @@ impl CodeWriter for InterfaceMap {
                         false,
                         |fmt| {
                             for (name, value) in ifaces {
-                                writeln!(fmt, "pub const {}: usize = {:#X};", name, value)?;
+                                writeln!(
+                                    fmt,
+                                    "pub const {}: usize = {:#X};",
+                                    rs_ident(name),
+                                    value
+                                )?;
                             }
 
                             Ok(())
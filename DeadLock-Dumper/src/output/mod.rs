@@ -1,29 +1,64 @@
+use std::collections::BTreeMap;
 use std::fmt::{self, Write};
 use std::fs;
+use std::io;
 use std::path::Path;
 
-use anyhow::{Result, anyhow};
+use anyhow::{Result, bail};
 
 use chrono::{DateTime, Utc};
 
-use memflow::prelude::v1::*;
+use clap::ValueEnum;
 
-use serde_json::json;
+use serde::Deserialize;
+
+use serde_json::{Value, json};
 
 use formatter::Formatter;
+use offsets::OffsetTable;
+
+pub use offsets::MODULE_KEY;
 
 use crate::analysis::*;
 
 mod buttons;
 mod formatter;
 mod interfaces;
+mod offset_ids;
 mod offsets;
 mod schemas;
 
+/// The version of the layout of the generated JSON files, written to each of them as a top-level
+/// `version` field. It's bumped whenever the layout changes in a way that parsers need to know
+/// about.
+///
+/// - `1`: Top-level `version` and `build_number` fields in every file, the base, size and image
+///   base of each module under `__module__` in `offsets.json`, and each field of a class as an
+///   object with its `offset` and its `metadata` array.
+pub const OUTPUT_SCHEMA_VERSION: u32 = 1;
+
+/// The top-level key of the generated JSON files that holds the settings they were generated with
+/// and the offsets that weren't found, if the settings are known.
+pub const META_KEY: &str = "_meta";
+
+/// The types of files that can be generated.
+pub const FILE_TYPES: &[&str] = &["cs", "dump", "h", "hpp", "json", "md", "py", "rs"];
+
+/// How offset values are written to the generated files.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum AddressMode {
+    /// Relative to the module base.
+    #[default]
+    Rva,
+    /// Absolute, using the module's preferred image base.
+    Absolute,
+}
+
 enum Item<'a> {
     Buttons(&'a ButtonMap),
     Interfaces(&'a InterfaceMap),
-    Offsets(&'a OffsetMap),
+    Offsets(OffsetTable<'a>),
     Schemas(&'a SchemaMap),
 }
 
@@ -85,10 +120,16 @@ impl<'a> CodeWriter for Item<'a> {
 }
 
 pub struct Output<'a> {
+    address_mode: AddressMode,
     file_types: &'a [String],
     indent_size: usize,
     out_dir: &'a Path,
     result: &'a AnalysisResult,
+    strip_prefix: Option<&'a str>,
+    /// The prologues as written to the JSON offsets, which use the stripped names.
+    prologues: PrologueMap,
+    /// Written as `settings` under [`META_KEY`], along with the missing offsets, if set.
+    settings: Option<Value>,
     timestamp: DateTime<Utc>,
 }
 
@@ -97,83 +138,501 @@ impl<'a> Output<'a> {
         file_types: &'a [String],
         indent_size: usize,
         out_dir: &'a Path,
+        address_mode: AddressMode,
         result: &'a AnalysisResult,
+        strip_prefix: Option<&'a str>,
     ) -> Result<Self> {
         fs::create_dir_all(&out_dir)?;
 
+        let prologues = match strip_prefix {
+            Some(prefix) => offsets::strip_prefix(&result.prologues, prefix)?,
+            None => result.prologues.clone(),
+        };
+
         Ok(Self {
+            address_mode,
             file_types,
             indent_size,
             out_dir,
             result,
+            strip_prefix,
+            prologues,
+            settings: None,
             timestamp: Utc::now(),
         })
     }
 
-    pub fn dump_all<P: MemoryView + Process>(&self, process: &mut P) -> Result<()> {
+    /// Stores the settings the files were generated with under [`META_KEY`] in each JSON file, so
+    /// a later dump can tell whether they changed.
+    pub fn with_settings(mut self, settings: Value) -> Self {
+        self.settings = Some(settings);
+        self
+    }
+
+    pub fn dump_all(&self) -> Result<()> {
         let items = [
             ("buttons", Item::Buttons(&self.result.buttons)),
             ("interfaces", Item::Interfaces(&self.result.interfaces)),
-            ("offsets", Item::Offsets(&self.result.offsets)),
         ];
 
         for (file_name, item) in &items {
             self.dump_item(file_name, item)?;
         }
 
+        self.dump_offsets()?;
         self.dump_schemas()?;
-        self.dump_info(process)?;
+        self.dump_info()?;
+
+        if self.file_types.iter().any(|file_type| file_type == "json") {
+            self.dump_enums()?;
+            self.dump_convars()?;
+            self.dump_vtables()?;
+            self.dump_interface_versions()?;
+            self.dump_chains()?;
+        }
+
+        if self.file_types.iter().any(|file_type| file_type == "dump") {
+            self.dump_combined()?;
+        }
 
         Ok(())
     }
 
-    fn dump_info<P: MemoryView + Process>(&self, process: &mut P) -> Result<()> {
-        let file_path = self.out_dir.join("info.json");
+    /// Writes only the files generated from the offsets, which are the `offsets.*` files and, if
+    /// requested, the IDAPython script, the Markdown table and the ID table.
+    pub fn dump_offsets(&self) -> Result<()> {
+        let offsets = self.offsets()?;
+
+        self.dump_item("offsets", &Item::Offsets(self.offset_table(&offsets)))?;
+
+        if self.file_types.iter().any(|file_type| file_type == "py") {
+            self.dump_ida_script()?;
+        }
+
+        if self.file_types.iter().any(|file_type| file_type == "md") {
+            self.dump_markdown()?;
+        }
+
+        if self.file_types.iter().any(|file_type| file_type == "h") {
+            self.dump_id_table()?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the offsets to `out` instead of a file, in the one file type that was requested.
+    pub fn write_offsets(&self, out: &mut impl io::Write) -> Result<()> {
+        let [file_type] = self.file_types else {
+            bail!(
+                "only one file type can be written to stdout, got {}",
+                self.file_types.len()
+            );
+        };
+
+        let content = if file_type == "py" {
+            self.ida_script()?
+        } else if file_type == "dump" {
+            self.combined()?
+        } else if file_type == "md" {
+            self.markdown()?
+        } else if file_type == "h" {
+            self.id_table()?
+        } else {
+            let offsets = self.offsets()?;
+
+            self.render_item(&Item::Offsets(self.offset_table(&offsets)), file_type)?
+        };
+
+        out.write_all(content.as_bytes())?;
+
+        Ok(())
+    }
+
+    fn offset_table<'b>(&'b self, offsets: &'b OffsetMap) -> OffsetTable<'b> {
+        OffsetTable {
+            offsets,
+            modules: &self.result.modules,
+            address_mode: self.address_mode,
+            build_number: self.build_number(),
+            prologues: Some(&self.prologues),
+        }
+    }
+
+    /// The build number, if it could be read, along with the module `dwBuildNumber` was found in.
+    fn build_number(&self) -> Option<(&str, u32)> {
+        let (module_name, _) = self
+            .result
+            .offsets
+            .iter()
+            .find(|(_, offsets)| offsets.contains_key("dwBuildNumber"))?;
+
+        Some((module_name, self.result.build_number?))
+    }
+
+    /// The offsets as written to the generated files, which use the stripped names.
+    fn offsets(&self) -> Result<OffsetMap> {
+        match self.strip_prefix {
+            Some(prefix) => offsets::strip_prefix(&self.result.offsets, prefix),
+            None => Ok(self.result.offsets.clone()),
+        }
+    }
+
+    /// Writes `patterns_debug.json`, which lists the source text of every scanned pattern along with
+    /// whether it matched, to help with fixing patterns after a game update.
+    pub fn dump_patterns_debug(&self) -> Result<()> {
+        let content: BTreeMap<_, _> = pattern_sources()
+            .into_iter()
+            .filter_map(|(name, (module_name, patterns))| {
+                let rva = self
+                    .result
+                    .offsets
+                    .get(module_name)
+                    .and_then(|offsets| offsets.get(name));
+
+                // Skip patterns that weren't scanned for at all.
+                if rva.is_none() && !self.result.missing_offsets.contains(name) {
+                    return None;
+                }
+
+                let sections = self.result.match_sections.get(name);
+
+                Some((
+                    name,
+                    json!({
+                        "module": module_name,
+                        "patterns": patterns,
+                        "matched": rva.is_some(),
+                        "matches": self.result.match_counts.get(name),
+                        "recovered": self.result.recovered_offsets.contains(name),
+                        "site_section": sections.and_then(|sections| sections.site.as_deref()),
+                        "section": sections.and_then(|sections| sections.value.as_deref()),
+                        "rva": rva.map(|rva| format!("{:#X}", rva)),
+                    }),
+                ))
+            })
+            .collect();
+
+        write_atomic(
+            &self.out_dir.join("patterns_debug.json"),
+            self.with_metadata(&serde_json::to_string(&content)?)?,
+        )?;
+
+        Ok(())
+    }
+
+    /// Writes `dump.json`, which combines the offsets, schemas, interfaces and buttons into a single
+    /// file.
+    fn dump_combined(&self) -> Result<()> {
+        write_atomic(&self.out_dir.join("dump.json"), self.combined()?)?;
+
+        Ok(())
+    }
+
+    /// The contents of the separate JSON files, each under its own top-level key.
+    fn combined(&self) -> Result<String> {
+        let offsets = self.offsets()?;
+
+        let json = |item: &Item| -> Result<Value> {
+            let mut out = String::new();
+
+            item.write_json(&mut Formatter::new(&mut out, self.indent_size))?;
+
+            Ok(serde_json::from_str(&out)?)
+        };
 
-        let build_number = self
+        let content = json!({
+            "offsets": json(&Item::Offsets(self.offset_table(&offsets)))?,
+            "schemas": json(&Item::Schemas(&self.sorted_schemas()))?,
+            "interfaces": json(&Item::Interfaces(&self.result.interfaces))?,
+            "buttons": json(&Item::Buttons(&self.result.buttons))?,
+        });
+
+        self.with_metadata(&content.to_string())
+    }
+
+    /// Writes `offsets.md`, a Markdown table of the offsets of each module for sharing them.
+    fn dump_markdown(&self) -> Result<()> {
+        write_atomic(&self.out_dir.join("offsets.md"), self.markdown()?)?;
+
+        Ok(())
+    }
+
+    fn markdown(&self) -> Result<String> {
+        let offsets = self.offsets()?;
+
+        let mut out = String::new();
+
+        self.offset_table(&offsets)
+            .write_markdown(&mut Formatter::new(&mut out, self.indent_size))?;
+
+        Ok(out)
+    }
+
+    /// Writes `offsets_table.h`, which assigns every offset a stable numeric ID for C consumers to
+    /// index an array of the values with.
+    fn dump_id_table(&self) -> Result<()> {
+        write_atomic(&self.out_dir.join("offsets_table.h"), self.id_table()?)?;
+
+        Ok(())
+    }
+
+    /// The IDs are registered under the original offset names, so the prefix isn't stripped.
+    fn id_table(&self) -> Result<String> {
+        let mut out = String::new();
+        let mut fmt = Formatter::new(&mut out, self.indent_size);
+
+        self.write_banner(&mut fmt, "h")?;
+
+        self.offset_table(&self.result.offsets)
+            .write_id_table(&mut fmt)?;
+
+        Ok(out)
+    }
+
+    /// Writes `offsets.py`, an IDAPython script that names the offsets pointing into a module.
+    fn dump_ida_script(&self) -> Result<()> {
+        write_atomic(&self.out_dir.join("offsets.py"), self.ida_script()?)?;
+
+        Ok(())
+    }
+
+    fn ida_script(&self) -> Result<String> {
+        // Struct offsets and the like don't correspond to a location in the module.
+        let addresses: OffsetMap = self
             .result
             .offsets
             .iter()
-            .find_map(|(module_name, offsets)| {
-                let module = process.module_by_name(module_name).ok()?;
-                let offset = offsets.iter().find(|(name, _)| *name == "dwBuildNumber")?.1;
+            .map(|(module_name, offsets)| {
+                let offsets = offsets
+                    .iter()
+                    .filter(|(name, _)| is_address(module_name, name))
+                    .map(|(name, rva)| (name.clone(), *rva))
+                    .collect();
+
+                (module_name.clone(), offsets)
+            })
+            .collect();
+
+        let addresses = match self.strip_prefix {
+            Some(prefix) => offsets::strip_prefix(&addresses, prefix)?,
+            None => addresses,
+        };
+
+        let table = OffsetTable {
+            offsets: &addresses,
+            modules: &self.result.modules,
+            address_mode: AddressMode::Rva,
+            build_number: None,
+            prologues: None,
+        };
+
+        let mut out = String::new();
+        let mut fmt = Formatter::new(&mut out, self.indent_size);
+
+        writeln!(fmt, "# Generated using DeadLock-Dumper\n")?;
+
+        table.write_ida(&mut fmt)?;
+
+        Ok(out)
+    }
+
+    /// Writes `enums.json`, which lists the schema enums of every module along with the size of
+    /// their underlying type. Members are listed both by name and by value, where several members
+    /// can share the same value.
+    fn dump_enums(&self) -> Result<()> {
+        let content: BTreeMap<_, _> = self
+            .result
+            .schemas
+            .iter()
+            .map(|(module_name, (_, enums))| {
+                let enums: BTreeMap<_, _> = enums
+                    .iter()
+                    .map(|enum_| {
+                        let members: BTreeMap<_, _> = enum_
+                            .members
+                            .iter()
+                            .map(|member| (&member.name, member.value))
+                            .collect();
+
+                        let mut values = BTreeMap::<_, Vec<_>>::new();
+
+                        for member in &enum_.members {
+                            values.entry(member.value).or_default().push(&member.name);
+                        }
 
-                process.read::<u32>(module.base + offset).data_part().ok()
+                        // JSON keys have to be strings.
+                        let values: BTreeMap<_, _> = values
+                            .into_iter()
+                            .map(|(value, names)| (value.to_string(), names))
+                            .collect();
+
+                        (
+                            slugify(&enum_.name),
+                            json!({
+                                "size": enum_.type_size,
+                                "members": members,
+                                "values": values,
+                            }),
+                        )
+                    })
+                    .collect();
+
+                (module_name, enums)
             })
-            .ok_or(anyhow!("failed to read build number"))?;
+            .collect();
+
+        write_atomic(
+            &self.out_dir.join("enums.json"),
+            self.with_metadata(&serde_json::to_string(&content)?)?,
+        )?;
+
+        Ok(())
+    }
+
+    /// Writes `convars.json`, which lists every registered convar along with its flags and default
+    /// value.
+    fn dump_convars(&self) -> Result<()> {
+        write_atomic(
+            &self.out_dir.join("convars.json"),
+            self.with_metadata(&serde_json::to_string(&self.result.convars)?)?,
+        )?;
+
+        Ok(())
+    }
+
+    /// Writes `interface_versions.json`, which groups the interfaces of every module by their name
+    /// without the version suffix, so the latest version can be picked without knowing its number.
+    /// The versions of each interface are listed from highest to lowest.
+    fn dump_interface_versions(&self) -> Result<()> {
+        let content: BTreeMap<_, _> = self
+            .result
+            .interfaces
+            .iter()
+            .map(|(module_name, ifaces)| {
+                let mut versions = BTreeMap::<_, Vec<_>>::new();
+
+                for (name, rva) in ifaces {
+                    let (base_name, version) = split_version(name);
+
+                    versions
+                        .entry(base_name)
+                        .or_default()
+                        .push((version, name, rva));
+                }
+
+                let versions: BTreeMap<_, _> = versions
+                    .into_iter()
+                    .map(|(base_name, mut ifaces)| {
+                        ifaces.sort_by(|a, b| b.cmp(a));
+
+                        let ifaces: Vec<_> = ifaces
+                            .into_iter()
+                            .map(|(version, name, rva)| {
+                                json!({
+                                    "name": name,
+                                    "version": version,
+                                    "rva": rva,
+                                })
+                            })
+                            .collect();
+
+                        (base_name, ifaces)
+                    })
+                    .collect();
+
+                (module_name, versions)
+            })
+            .collect();
+
+        write_atomic(
+            &self.out_dir.join("interface_versions.json"),
+            self.with_metadata(&serde_json::to_string(&content)?)?,
+        )?;
+
+        Ok(())
+    }
+
+    /// Writes `chains.json`, which lists the hops of every pointer chain in [`CHAINS`] for a
+    /// generic reader to follow. The offsets are always RVAs and use their original names.
+    fn dump_chains(&self) -> Result<()> {
+        let chains = resolve_chains(&self.result.offsets, &self.result.schemas);
+
+        write_atomic(
+            &self.out_dir.join("chains.json"),
+            self.with_metadata(&serde_json::to_string(&chains)?)?,
+        )?;
+
+        Ok(())
+    }
+
+    /// Writes `vtables.json`, which lists the RVA and number of methods of the vtable of every
+    /// class it was found for.
+    fn dump_vtables(&self) -> Result<()> {
+        write_atomic(
+            &self.out_dir.join("vtables.json"),
+            self.with_metadata(&serde_json::to_string(&self.result.vtables)?)?,
+        )?;
+
+        Ok(())
+    }
+
+    fn dump_info(&self) -> Result<()> {
+        let file_path = self.out_dir.join("info.json");
+
+        let address_mode = self
+            .address_mode
+            .to_possible_value()
+            .map(|value| value.get_name().to_string());
 
         let content = serde_json::to_string_pretty(&json!({
+            "version": OUTPUT_SCHEMA_VERSION,
             "timestamp": self.timestamp.to_rfc3339(),
-            "build_number": build_number,
+            "build_number": self.result.build_number,
+            "address_mode": address_mode,
+            "modules": self.result.modules,
         }))?;
 
-        fs::write(&file_path, &content)?;
+        write_atomic(&file_path, &content)?;
 
         Ok(())
     }
 
     fn dump_item(&self, file_name: &str, item: &Item) -> Result<()> {
         for file_type in self.file_types {
-            let mut out = String::new();
-            let mut fmt = Formatter::new(&mut out, self.indent_size);
-
-            if file_type != "json" {
-                self.write_banner(&mut fmt)?;
+            // IDA scripts, Markdown tables, the ID table and the combined dump are written
+            // separately.
+            if matches!(file_type.as_str(), "dump" | "h" | "md" | "py") {
+                continue;
             }
 
-            item.write(&mut fmt, file_type)?;
-
             let file_path = self.out_dir.join(format!("{}.{}", file_name, file_type));
 
-            fs::write(&file_path, out)?;
+            write_atomic(&file_path, self.render_item(item, file_type)?)?;
         }
 
         Ok(())
     }
 
+    fn render_item(&self, item: &Item, file_type: &str) -> Result<String> {
+        let mut out = String::new();
+        let mut fmt = Formatter::new(&mut out, self.indent_size);
+
+        if file_type != "json" {
+            self.write_banner(&mut fmt, file_type)?;
+        }
+
+        item.write(&mut fmt, file_type)?;
+
+        if file_type == "json" {
+            out = self.with_metadata(&out)?;
+        }
+
+        Ok(out)
+    }
+
     fn dump_schemas(&self) -> Result<()> {
-        for (module_name, (classes, enums)) in &self.result.schemas {
-            let map = SchemaMap::from([(module_name.clone(), (classes.clone(), enums.clone()))]);
+        for (module_name, types) in self.sorted_schemas() {
+            let map = SchemaMap::from([(module_name.clone(), types)]);
 
             self.dump_item(&slugify(&module_name), &Item::Schemas(&map))?;
         }
@@ -181,15 +640,402 @@ impl<'a> Output<'a> {
         Ok(())
     }
 
-    fn write_banner(&self, fmt: &mut Formatter<'_>) -> Result<()> {
+    /// The types are read from hash tables in the game's memory, so their order can change between
+    /// runs. Sorting them keeps the generated files stable.
+    fn sorted_schemas(&self) -> SchemaMap {
+        let mut schemas = self.result.schemas.clone();
+
+        for (classes, enums) in schemas.values_mut() {
+            classes.sort_by(|a, b| a.name.cmp(&b.name));
+            enums.sort_by(|a, b| a.name.cmp(&b.name));
+        }
+
+        schemas
+    }
+
+    /// Adds the top-level `version` and `build_number` fields to a generated JSON file, and the
+    /// settings along with the missing offsets under [`META_KEY`] if they're set. The build number
+    /// is `null` if it couldn't be read.
+    fn with_metadata(&self, content: &str) -> Result<String> {
+        let mut value: Value = serde_json::from_str(content)?;
+
+        if let Some(map) = value.as_object_mut() {
+            map.insert("version".to_string(), OUTPUT_SCHEMA_VERSION.into());
+            map.insert("build_number".to_string(), self.result.build_number.into());
+
+            if let Some(settings) = &self.settings {
+                map.insert(
+                    META_KEY.to_string(),
+                    json!({
+                        "settings": settings,
+                        "missing_offsets": self.result.missing_offsets,
+                    }),
+                );
+            }
+        }
+
+        Ok(serde_json::to_string_pretty(&value)?)
+    }
+
+    fn write_banner(&self, fmt: &mut Formatter<'_>, file_type: &str) -> Result<()> {
+        // The time of the dump is only written to `info.json`, so the code stays the same between
+        // dumps of the same build.
         writeln!(fmt, "// Generated using DeadLock-Dumper")?;
-        writeln!(fmt, "// {}\n", self.timestamp)?;
+
+        if file_type == "rs" {
+            writeln!(fmt, "// @generated")?;
+        }
+
+        if let Some(build_number) = self.result.build_number {
+            writeln!(fmt, "// Build number: {}", build_number)?;
+        }
+
+        writeln!(fmt)?;
 
         Ok(())
     }
 }
 
+/// Writes to a temporary file first and then renames it, so that readers never observe a partially
+/// written file.
+fn write_atomic(path: &Path, content: impl AsRef<[u8]>) -> io::Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+
+    tmp_path.push(".tmp");
+
+    fs::write(&tmp_path, content)?;
+    fs::rename(&tmp_path, path)
+}
+
 #[inline]
 fn slugify(input: &str) -> String {
     input.replace(|c: char| !c.is_alphanumeric(), "_")
 }
+
+/// Turns a name into a valid Rust identifier, using a raw identifier for reserved keywords.
+fn rs_ident(name: &str) -> String {
+    #[rustfmt::skip]
+    const KEYWORDS: [&str; 35] = [
+        "as", "async", "await", "break", "const", "continue", "dyn", "else", "enum", "extern",
+        "false", "fn", "for", "gen", "if", "impl", "in", "let", "loop", "match", "mod", "move",
+        "mut", "pub", "ref", "return", "static", "struct", "trait", "true", "type", "unsafe",
+        "use", "where", "while",
+    ];
+
+    match name {
+        // These can't be used as raw identifiers.
+        "crate" | "self" | "Self" | "super" => format!("{}_", name),
+        _ if name.starts_with(|c: char| c.is_ascii_digit()) => format!("_{}", name),
+        _ if KEYWORDS.contains(&name) => format!("r#{}", name),
+        _ => name.to_string(),
+    }
+}
+
+/// Escapes identifiers that collide with a reserved C# keyword.
+fn cs_ident(name: &str) -> String {
+    #[rustfmt::skip]
+    const KEYWORDS: [&str; 77] = [
+        "abstract", "as", "base", "bool", "break", "byte", "case", "catch", "char", "checked",
+        "class", "const", "continue", "decimal", "default", "delegate", "do", "double", "else",
+        "enum", "event", "explicit", "extern", "false", "finally", "fixed", "float", "for",
+        "foreach", "goto", "if", "implicit", "in", "int", "interface", "internal", "is", "lock",
+        "long", "namespace", "new", "null", "object", "operator", "out", "override", "params",
+        "private", "protected", "public", "readonly", "ref", "return", "sbyte", "sealed", "short",
+        "sizeof", "stackalloc", "static", "string", "struct", "switch", "this", "throw", "true",
+        "try", "typeof", "uint", "ulong", "unchecked", "unsafe", "ushort", "using", "virtual",
+        "void", "volatile", "while",
+    ];
+
+    if KEYWORDS.contains(&name) {
+        format!("@{}", name)
+    } else {
+        name.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+
+    use crate::analysis::fixtures::class;
+
+    fn enum_(name: &str) -> Enum {
+        Enum {
+            name: name.to_string(),
+            alignment: 4,
+            type_size: 4,
+            size: 1,
+            members: vec![EnumMember {
+                name: "NONE".to_string(),
+                value: 0,
+            }],
+        }
+    }
+
+    fn dump(dir: &Path, result: &AnalysisResult) -> Result<()> {
+        let file_types = ["cs", "dump", "h", "hpp", "json", "md", "py", "rs"].map(String::from);
+
+        Output::new(&file_types, 4, dir, AddressMode::Rva, result, None)?.dump_all()
+    }
+
+    #[test]
+    fn stable_output() -> Result<()> {
+        let offsets = OffsetMap::from([
+            (
+                "client.dll".to_string(),
+                BTreeMap::from([
+                    ("dwViewMatrix".to_string(), 0x200),
+                    ("dwEntityList".to_string(), 0x100),
+                ]),
+            ),
+            (
+                "engine2.dll".to_string(),
+                BTreeMap::from([("dwBuildNumber".to_string(), 0x300)]),
+            ),
+        ]);
+
+        let result = |classes: Vec<Class>, enums: Vec<Enum>| AnalysisResult {
+            offsets: offsets.clone(),
+            schemas: SchemaMap::from([("client.dll".to_string(), (classes, enums))]),
+            ..Default::default()
+        };
+
+        // The same types, but read in a different order.
+        let first = result(
+            vec![
+                class("C_BaseEntity", None, &[("m_iHealth", 0x34C)]),
+                class("C_BaseModelEntity", None, &[("m_iHealth", 0x34C)]),
+            ],
+            vec![enum_("LifeState_t"), enum_("MoveType_t")],
+        );
+
+        let second = result(
+            vec![
+                class("C_BaseModelEntity", None, &[("m_iHealth", 0x34C)]),
+                class("C_BaseEntity", None, &[("m_iHealth", 0x34C)]),
+            ],
+            vec![enum_("MoveType_t"), enum_("LifeState_t")],
+        );
+
+        let temp = tempfile::tempdir()?;
+        let dirs = ["first", "second"].map(|name| temp.path().join(name));
+
+        dump(&dirs[0], &first)?;
+        dump(&dirs[1], &second)?;
+
+        let mut file_names: Vec<_> = fs::read_dir(&dirs[0])?
+            .map(|entry| Ok(entry?.file_name()))
+            .collect::<io::Result<_>>()?;
+
+        file_names.sort();
+
+        assert!(!file_names.is_empty());
+
+        // Only `info.json` has the time of the dump.
+        file_names.retain(|file_name| file_name != "info.json");
+
+        for file_name in file_names {
+            assert_eq!(
+                fs::read(dirs[0].join(&file_name))?,
+                fs::read(dirs[1].join(&file_name))?,
+                "{:?} differs between runs",
+                file_name
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn offsets_round_trip() -> Result<()> {
+        let result = AnalysisResult {
+            build_number: Some(5812),
+            modules: ModuleMap::from([(
+                "engine2.dll".to_string(),
+                ModuleMetadata {
+                    base: Some(0x7FF800000000),
+                    size: Some(0x600000),
+                    image_base: 0x180000000,
+                    text_hash: Some("0123456789ABCDEF".to_string()),
+                },
+            )]),
+            offsets: OffsetMap::from([
+                (
+                    "client.dll".to_string(),
+                    BTreeMap::from([
+                        ("dwEntityList".to_string(), 0x100),
+                        ("fnGetCmd".to_string(), 0x200),
+                    ]),
+                ),
+                (
+                    "engine2.dll".to_string(),
+                    BTreeMap::from([("dwBuildNumber".to_string(), 0x300)]),
+                ),
+            ]),
+            prologues: PrologueMap::from([(
+                "client.dll".to_string(),
+                BTreeMap::from([("fnGetCmd".to_string(), "48 83 EC 28".to_string())]),
+            )]),
+            ..Default::default()
+        };
+
+        let temp = tempfile::tempdir()?;
+        let dir = temp.path();
+
+        dump(dir, &result)?;
+
+        let content: Value = serde_json::from_str(&fs::read_to_string(dir.join("offsets.json"))?)?;
+
+        assert_eq!(content["client.dll"]["fnGetCmd_prologue"], "48 83 EC 28");
+
+        // The prologues are skipped when loading the offsets back.
+        let (offsets, modules, build_number) = load_offsets(&dir.join("offsets.json"))?;
+
+        assert_eq!(offsets, result.offsets);
+        assert_eq!(build_number, Some(5812));
+
+        let typed = RvaMap::load(&dir.join("offsets.json"))?;
+
+        assert_eq!(typed, RvaMap(result.offsets.clone()));
+        assert_eq!(typed.get("client.dll", "dwEntityList"), Some(0x100));
+        assert_eq!(typed.get("client.dll", "dwViewMatrix"), None);
+        assert_eq!(
+            serde_json::from_str::<RvaMap>(&serde_json::to_string(&typed)?)?,
+            typed
+        );
+
+        let typed = Offsets::load(&dir.join("offsets.json"))?;
+
+        assert_eq!(typed.client.dw_entity_list, Some(0x100));
+        assert_eq!(typed.client.fn_get_cmd, Some(0x200));
+        assert!(typed.client.other.is_empty());
+        assert_eq!(typed.engine2.dw_build_number, Some(0x300));
+
+        // The hash of the code isn't written to `offsets.json`.
+        let module = &modules["engine2.dll"];
+
+        assert_eq!(module.base, Some(0x7FF800000000));
+        assert_eq!(module.size, Some(0x600000));
+        assert_eq!(module.image_base, 0x180000000);
+        assert!(!modules.contains_key("client.dll"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn settings() -> Result<()> {
+        let result = AnalysisResult {
+            build_number: Some(5812),
+            missing_offsets: BTreeSet::from(["dwViewMatrix".to_string()]),
+            ..Default::default()
+        };
+
+        let temp = tempfile::tempdir()?;
+        let dir = temp.path();
+        let file_types = ["json".to_string()];
+
+        Output::new(&file_types, 4, dir, AddressMode::Rva, &result, None)?
+            .with_settings(json!({ "formats": ["json"] }))
+            .dump_offsets()?;
+
+        let content: Value = serde_json::from_str(&fs::read_to_string(dir.join("offsets.json"))?)?;
+
+        assert_eq!(content["version"], OUTPUT_SCHEMA_VERSION);
+        assert_eq!(content["build_number"], 5812);
+        assert_eq!(content[META_KEY]["settings"]["formats"], json!(["json"]));
+        assert_eq!(
+            content[META_KEY]["missing_offsets"],
+            json!(["dwViewMatrix"])
+        );
+
+        Output::new(&file_types, 4, dir, AddressMode::Rva, &result, None)?.dump_offsets()?;
+
+        let content: Value = serde_json::from_str(&fs::read_to_string(dir.join("offsets.json"))?)?;
+
+        assert!(content.get(META_KEY).is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn interface_versions() -> Result<()> {
+        let result = AnalysisResult {
+            interfaces: InterfaceMap::from([(
+                "client.dll".to_string(),
+                BTreeMap::from([
+                    ("Source2Client002".to_string(), 0x100),
+                    ("Source2Client010".to_string(), 0x200),
+                    ("GameClientExports001".to_string(), 0x300),
+                ]),
+            )]),
+            ..Default::default()
+        };
+
+        let temp = tempfile::tempdir()?;
+        let dir = temp.path();
+
+        dump(dir, &result)?;
+
+        let content: Value =
+            serde_json::from_str(&fs::read_to_string(dir.join("interface_versions.json"))?)?;
+
+        let versions = &content["client.dll"]["Source2Client"];
+
+        assert_eq!(versions[0]["name"], "Source2Client010");
+        assert_eq!(versions[0]["version"], 10);
+        assert_eq!(versions[0]["rva"], 0x200);
+        assert_eq!(versions[1]["version"], 2);
+        assert_eq!(content["client.dll"]["GameClientExports"][0]["rva"], 0x300);
+
+        Ok(())
+    }
+
+    #[test]
+    fn combined_dump() -> Result<()> {
+        let result = AnalysisResult {
+            build_number: Some(5_800),
+            buttons: ButtonMap::from([("attack".to_string(), 0x10)]),
+            offsets: OffsetMap::from([(
+                "engine2.dll".to_string(),
+                BTreeMap::from([("dwBuildNumber".to_string(), 0x300)]),
+            )]),
+            schemas: SchemaMap::from([(
+                "client.dll".to_string(),
+                (
+                    vec![class("C_BaseEntity", None, &[("m_iHealth", 0x34C)])],
+                    vec![enum_("LifeState_t")],
+                ),
+            )]),
+            ..Default::default()
+        };
+
+        let temp = tempfile::tempdir()?;
+        let dir = temp.path();
+
+        dump(dir, &result)?;
+
+        let content: Value = serde_json::from_str(&fs::read_to_string(dir.join("dump.json"))?)?;
+
+        assert_eq!(content["build_number"], 5_800);
+        assert_eq!(content["offsets"]["engine2.dll"]["dwBuildNumber"], 0x300);
+        assert!(
+            content["offsets"]["engine2.dll"]
+                .get("build_number")
+                .is_none()
+        );
+        assert_eq!(content["buttons"]["client.dll"]["attack"], 0x10);
+        assert!(content["interfaces"].is_object());
+
+        assert_eq!(
+            content["schemas"]["client.dll"]["classes"]["C_BaseEntity"]["fields"]["m_iHealth"],
+            json!({ "offset": 0x34C, "metadata": [] })
+        );
+
+        // The separate files are still written alongside it.
+        assert!(dir.join("offsets.json").exists());
+
+        Ok(())
+    }
+}
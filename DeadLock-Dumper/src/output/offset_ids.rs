@@ -0,0 +1,88 @@
+/// The numeric ID of every offset written to `offsets_table.h`, keyed by module and offset name.
+///
+/// IDs are part of the header's ABI, so they must never change or be reused: new offsets are
+/// appended with the next free ID, and the entries of removed offsets are deleted, leaving a gap.
+#[rustfmt::skip]
+pub(super) const OFFSET_IDS: &[(u32, &str, &str)] = &[
+    (0,  "client.dll",       "dwEntityList"),
+    (1,  "client.dll",       "dwEntityList_chunkSize"),
+    (2,  "client.dll",       "dwEntityList_entrySize"),
+    (3,  "client.dll",       "dwGameEntitySystem"),
+    (4,  "client.dll",       "dwLocalPlayerController"),
+    (5,  "client.dll",       "dwLocalPlayerPawn"),
+    (6,  "client.dll",       "dwViewMatrix"),
+    (7,  "client.dll",       "dwCCitadelCameraManager"),
+    (8,  "client.dll",       "dwGlobalVars"),
+    (9,  "client.dll",       "dwGameTraceManager"),
+    (10, "client.dll",       "fnGetCmd"),
+    (11, "client.dll",       "fnDecodeNetworkEntities"),
+    (12, "client.dll",       "dwSchemas"),
+    (13, "client.dll",       "dwMaterialSystem"),
+    (14, "client.dll",       "dwPrediction"),
+    (15, "client.dll",       "dwGameRules"),
+    (16, "client.dll",       "dwObjectiveManager"),
+    (17, "client.dll",       "dwMidbossManager"),
+    (18, "client.dll",       "dwTubeManager"),
+    (19, "engine2.dll",      "dwBuildNumber"),
+    (20, "engine2.dll",      "dwNetworkGameClient"),
+    (21, "engine2.dll",      "dwNetworkGameClient_clientTickCount"),
+    (22, "engine2.dll",      "dwNetworkGameClient_deltaTick"),
+    (23, "engine2.dll",      "dwNetworkGameClient_isBackgroundMap"),
+    (24, "engine2.dll",      "dwNetworkGameClient_localPlayer"),
+    (25, "engine2.dll",      "dwNetworkGameClient_maxClients"),
+    (26, "engine2.dll",      "dwNetworkGameClient_serverTickCount"),
+    (27, "engine2.dll",      "dwNetworkGameClient_signOnState"),
+    (28, "engine2.dll",      "dwWindowHeight"),
+    (29, "engine2.dll",      "dwWindowWidth"),
+    (30, "schemasystem.dll", "dwSchemaSystem"),
+    (31, "inputsystem.dll",  "dwInputSystem"),
+    (32, "inputsystem.dll",  "dwSensitivityPtr"),
+    (33, "inputsystem.dll",  "dwSensitivity"),
+    (34, "client.dll",       "dwGameEntitySystem_maxEntityIndex"),
+    (35, "client.dll",       "dwGameEntitySystem_identityCount"),
+    (36, "tier0.dll",        "dwPerformanceCounterStart"),
+    (37, "tier0.dll",        "dwPerformanceCounterToSeconds"),
+    (38, "client.dll",       "dwViewRender"),
+    (39, "client.dll",       "dwViewAngles"),
+];
+
+/// The ID of the offset `name` in `module_name`, if it has one.
+pub(super) fn offset_id(module_name: &str, name: &str) -> Option<u32> {
+    OFFSET_IDS
+        .iter()
+        .find(|(_, module, offset)| *module == module_name && *offset == name)
+        .map(|(id, ..)| *id)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    use crate::analysis::pattern_sources;
+
+    #[test]
+    fn registry() {
+        let mut ids = HashSet::new();
+
+        for (id, module_name, name) in OFFSET_IDS {
+            assert!(ids.insert(id), "ID {} is used more than once", id);
+            assert_eq!(
+                offset_id(module_name, name),
+                Some(*id),
+                "{} is registered twice",
+                name
+            );
+        }
+
+        for (name, (module_name, _)) in pattern_sources() {
+            assert!(
+                offset_id(module_name, name).is_some(),
+                "{} in {} has no ID",
+                name,
+                module_name
+            );
+        }
+    }
+}
@@ -1,13 +1,265 @@
+use std::collections::BTreeMap;
 use std::fmt::{self, Write};
 
+use anyhow::{Result, bail};
+
 use heck::{AsPascalCase, AsSnakeCase};
 
-use super::{CodeWriter, Formatter, OffsetMap, slugify};
+use pelite::pe64::Rva;
+
+use serde_json::json;
+
+use super::offset_ids::{OFFSET_IDS, offset_id};
+use super::{
+    AddressMode, CodeWriter, Formatter, ModuleMap, OffsetMap, PROLOGUE_SUFFIX, PrologueMap,
+    cs_ident, rs_ident, slugify,
+};
+
+/// The key the location of each module is written under in `offsets.json`, which can't collide
+/// with an offset name.
+pub const MODULE_KEY: &str = "__module__";
+
+#[derive(Clone, Copy)]
+pub struct OffsetTable<'a> {
+    pub offsets: &'a OffsetMap,
+    /// The metadata of each module, whose preferred image base is written as `image_base` next to
+    /// its offsets, so they can be turned into addresses without the module being loaded.
+    pub modules: &'a ModuleMap,
+    pub address_mode: AddressMode,
+    /// The value of the build number, written as `build_number` next to the offsets of the module
+    /// it was read from, and in the doc comment of the Rust module. The JSON file only has it as a
+    /// top-level field, so it isn't mistaken for an offset.
+    pub build_number: Option<(&'a str, u32)>,
+    /// The bytes each function starts with, written to the JSON file as `<name>_prologue` next to
+    /// the offsets.
+    pub prologues: Option<&'a PrologueMap>,
+}
+
+/// Strips `prefix` from every offset name that starts with it, failing if that makes two names in
+/// the same module collide.
+pub fn strip_prefix<T: Clone>(
+    offsets: &BTreeMap<String, BTreeMap<String, T>>,
+    prefix: &str,
+) -> Result<BTreeMap<String, BTreeMap<String, T>>> {
+    offsets
+        .iter()
+        .map(|(module_name, offsets)| {
+            let mut stripped = BTreeMap::new();
+
+            for (name, rva) in offsets {
+                let new_name = name
+                    .strip_prefix(prefix)
+                    .filter(|name| !name.is_empty())
+                    .unwrap_or(name);
+
+                if stripped.insert(new_name.to_string(), rva.clone()).is_some() {
+                    bail!(
+                        "stripping \"{}\" from \"{}\" collides with another offset in {}",
+                        prefix,
+                        name,
+                        module_name
+                    );
+                }
+            }
+
+            Ok((module_name.clone(), stripped))
+        })
+        .collect()
+}
+
+/// The namespace the offsets of a module are written to in the C++ and Rust files, which is its
+/// name without the `.dll` extension, e.g. `client` for `client.dll`.
+fn module_namespace(module_name: &str) -> String {
+    let name = module_name.strip_suffix(".dll").unwrap_or(module_name);
+
+    AsSnakeCase(slugify(name)).to_string()
+}
+
+impl OffsetTable<'_> {
+    fn modules(&self) -> impl Iterator<Item = (&String, BTreeMap<&String, u64>)> {
+        self.offsets.iter().map(move |(module_name, offsets)| {
+            let offsets = offsets
+                .iter()
+                .map(|(name, rva)| (name, self.value(module_name, *rva)))
+                .collect();
+
+            (module_name, offsets)
+        })
+    }
+
+    fn build_number(&self, module_name: &str) -> Option<u32> {
+        self.build_number
+            .filter(|(name, _)| *name == module_name)
+            .map(|(_, build_number)| build_number)
+    }
+
+    fn image_base(&self, module_name: &str) -> Option<u64> {
+        self.modules
+            .get(module_name)
+            .map(|module| module.image_base)
+    }
+
+    fn value(&self, module_name: &str, rva: Rva) -> u64 {
+        match self.address_mode {
+            AddressMode::Rva => rva as u64,
+            AddressMode::Absolute => {
+                let image_base = self
+                    .modules
+                    .get(module_name)
+                    .map_or(0, |module| module.image_base);
 
-impl CodeWriter for OffsetMap {
+                image_base + rva as u64
+            }
+        }
+    }
+}
+
+impl OffsetTable<'_> {
+    /// Writes an IDAPython script that names the offsets of whichever module is loaded in the
+    /// database, relative to the image base IDA loaded it at.
+    pub fn write_ida(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(fmt, "import idaapi")?;
+        writeln!(fmt, "import idc\n")?;
+
+        for (module_name, offsets) in self.offsets {
+            writeln!(fmt, "# Module: {}", module_name)?;
+            writeln!(
+                fmt,
+                "def label_{}(base):",
+                AsSnakeCase(slugify(module_name))
+            )?;
+
+            fmt.indent(|fmt| {
+                if offsets.is_empty() {
+                    writeln!(fmt, "pass")?;
+                }
+
+                for (name, rva) in offsets {
+                    writeln!(
+                        fmt,
+                        "idc.set_name(base + {:#X}, \"{}\", idc.SN_NOWARN)",
+                        rva, name
+                    )?;
+                }
+
+                Ok(())
+            })?;
+
+            writeln!(fmt)?;
+        }
+
+        writeln!(fmt, "LABELERS = {{")?;
+
+        fmt.indent(|fmt| {
+            for module_name in self.offsets.keys() {
+                writeln!(
+                    fmt,
+                    "\"{}\": label_{},",
+                    module_name,
+                    AsSnakeCase(slugify(module_name))
+                )?;
+            }
+
+            Ok(())
+        })?;
+
+        writeln!(fmt, "}}\n")?;
+        writeln!(fmt, "module_name = idaapi.get_root_filename().lower()\n")?;
+        writeln!(fmt, "if module_name in LABELERS:")?;
+        writeln!(fmt, "    LABELERS[module_name](idaapi.get_imagebase())")?;
+        writeln!(fmt, "else:")?;
+        writeln!(
+            fmt,
+            "    print(\"no offsets for {{}}\".format(module_name))"
+        )
+    }
+}
+
+impl OffsetTable<'_> {
+    /// Writes a Markdown table of the offsets of each module, with both their RVA and their
+    /// absolute address at the module's preferred image base.
+    pub fn write_markdown(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
+        match self.build_number {
+            Some((_, build_number)) => writeln!(fmt, "# Offsets (build {})\n", build_number)?,
+            None => writeln!(fmt, "# Offsets\n")?,
+        }
+
+        for (module_name, offsets) in self.offsets {
+            let image_base = self
+                .modules
+                .get(module_name)
+                .map(|module| module.image_base);
+
+            writeln!(fmt, "## {}\n", module_name)?;
+            writeln!(fmt, "| Name | RVA | Address |")?;
+            writeln!(fmt, "| --- | --- | --- |")?;
+
+            for (name, rva) in offsets {
+                match image_base {
+                    Some(image_base) => writeln!(
+                        fmt,
+                        "| `{}` | `{:#X}` | `{:#X}` |",
+                        name,
+                        rva,
+                        image_base + *rva as u64
+                    )?,
+                    None => writeln!(fmt, "| `{}` | `{:#X}` | - |", name, rva)?,
+                }
+            }
+
+            writeln!(fmt)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl OffsetTable<'_> {
+    /// Writes a C header with an enum of the registered offset IDs and an array of the offset
+    /// values indexed by them. Offsets without an ID, e.g. sites, are left out, and the values of
+    /// those that weren't found are 0.
+    pub fn write_id_table(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
+        let ident = |module_name: &str, name: &str| {
+            format!("DEADLOCK_OFFSET_{}_{}", slugify(module_name), name)
+        };
+
+        let count = OFFSET_IDS.iter().map(|(id, ..)| id + 1).max().unwrap_or(0);
+
+        writeln!(fmt, "#pragma once\n")?;
+        writeln!(fmt, "#include <stdint.h>\n")?;
+
+        fmt.block("enum deadlock_offset_id", true, |fmt| {
+            for (id, module_name, name) in OFFSET_IDS {
+                writeln!(fmt, "{} = {},", ident(module_name, name), id)?;
+            }
+
+            writeln!(fmt, "DEADLOCK_OFFSET_COUNT = {},", count)
+        })?;
+
+        writeln!(fmt)?;
+
+        fmt.block(
+            "static const uintptr_t deadlock_offsets[DEADLOCK_OFFSET_COUNT] =",
+            true,
+            |fmt| {
+                for (module_name, offsets) in self.modules() {
+                    for (name, value) in offsets {
+                        if offset_id(module_name, name).is_some() {
+                            writeln!(fmt, "[{}] = {:#X},", ident(module_name, name), value)?;
+                        }
+                    }
+                }
+
+                Ok(())
+            },
+        )
+    }
+}
+
+impl CodeWriter for OffsetTable<'_> {
     fn write_cs(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
         fmt.block("namespace CS2Dumper.Offsets", false, |fmt| {
-            for (module_name, offsets) in self {
+            for (module_name, offsets) in self.modules() {
                 writeln!(fmt, "// Module: {}", module_name)?;
 
                 fmt.block(
@@ -15,7 +267,29 @@ impl CodeWriter for OffsetMap {
                     false,
                     |fmt| {
                         for (name, value) in offsets {
-                            writeln!(fmt, "public const nint {} = {:#X};", name, value)?;
+                            if value > i32::MAX as u64 {
+                                writeln!(
+                                    fmt,
+                                    "public static readonly nint {} = unchecked((nint){:#X});",
+                                    cs_ident(name),
+                                    value
+                                )?;
+                            } else {
+                                writeln!(
+                                    fmt,
+                                    "public const nint {} = {:#X};",
+                                    cs_ident(name),
+                                    value
+                                )?;
+                            }
+                        }
+
+                        if let Some(build_number) = self.build_number(module_name) {
+                            writeln!(fmt, "public const uint build_number = {};", build_number)?;
+                        }
+
+                        if let Some(image_base) = self.image_base(module_name) {
+                            writeln!(fmt, "public const ulong image_base = {:#X};", image_base)?;
                         }
 
                         Ok(())
@@ -34,17 +308,33 @@ impl CodeWriter for OffsetMap {
 
         fmt.block("namespace deadlock_dumper", false, |fmt| {
             fmt.block("namespace offsets", false, |fmt| {
-                for (module_name, offsets) in self {
+                for (module_name, offsets) in self.modules() {
                     writeln!(fmt, "// Module: {}", module_name)?;
 
                     fmt.block(
-                        &format!("namespace {}", AsSnakeCase(slugify(module_name))),
+                        &format!("namespace {}", module_namespace(module_name)),
                         false,
                         |fmt| {
                             for (name, value) in offsets {
                                 writeln!(fmt, "constexpr std::ptrdiff_t {} = {:#X};", name, value)?;
                             }
 
+                            if let Some(build_number) = self.build_number(module_name) {
+                                writeln!(
+                                    fmt,
+                                    "constexpr std::uint32_t build_number = {};",
+                                    build_number
+                                )?;
+                            }
+
+                            if let Some(image_base) = self.image_base(module_name) {
+                                writeln!(
+                                    fmt,
+                                    "constexpr std::uintptr_t image_base = {:#X};",
+                                    image_base
+                                )?;
+                            }
+
                             Ok(())
                         },
                     )?;
@@ -56,23 +346,75 @@ impl CodeWriter for OffsetMap {
     }
 
     fn write_json(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
-        fmt.write_str(&serde_json::to_string_pretty(self).unwrap())
+        let content: BTreeMap<_, _> = self
+            .modules()
+            .map(|(module_name, offsets)| {
+                let mut offsets = serde_json::to_value(offsets).unwrap();
+
+                let prologues = self
+                    .prologues
+                    .and_then(|prologues| prologues.get(module_name))
+                    .into_iter()
+                    .flatten();
+
+                for (name, prologue) in prologues {
+                    offsets[format!("{}{}", name, PROLOGUE_SUFFIX)] = prologue.as_str().into();
+                }
+
+                if let Some(module) = self.modules.get(module_name) {
+                    offsets[MODULE_KEY] = json!({
+                        "base": module.base,
+                        "size": module.size,
+                        "image_base": module.image_base,
+                    });
+                }
+
+                (module_name, offsets)
+            })
+            .collect();
+
+        fmt.write_str(&serde_json::to_string_pretty(&content).unwrap())
     }
 
     fn write_rs(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
-        writeln!(fmt, "#![allow(non_upper_case_globals, unused)]\n")?;
+        // RVAs always fit in 32 bits, but absolute addresses don't.
+        let value_type = match self.address_mode {
+            AddressMode::Rva => "u32",
+            AddressMode::Absolute => "u64",
+        };
+
+        if let Some((_, build_number)) = self.build_number {
+            writeln!(fmt, "/// Build number: {}", build_number)?;
+        }
+
+        // An outer attribute, so the file can also be pulled in with `include!`.
+        writeln!(fmt, "#[allow(non_upper_case_globals, unused)]")?;
 
         fmt.block("pub mod deadlock_dumper", false, |fmt| {
             fmt.block("pub mod offsets", false, |fmt| {
-                for (module_name, offsets) in self {
+                for (module_name, offsets) in self.modules() {
                     writeln!(fmt, "// Module: {}", module_name)?;
 
                     fmt.block(
-                        &format!("pub mod {}", AsSnakeCase(slugify(module_name))),
+                        &format!("pub mod {}", module_namespace(module_name)),
                         false,
                         |fmt| {
                             for (name, value) in offsets {
-                                writeln!(fmt, "pub const {}: usize = {:#X};", name, value)?;
+                                writeln!(
+                                    fmt,
+                                    "pub const {}: {} = {:#X};",
+                                    rs_ident(name),
+                                    value_type,
+                                    value
+                                )?;
+                            }
+
+                            if let Some(build_number) = self.build_number(module_name) {
+                                writeln!(fmt, "pub const build_number: u32 = {};", build_number)?;
+                            }
+
+                            if let Some(image_base) = self.image_base(module_name) {
+                                writeln!(fmt, "pub const image_base: usize = {:#X};", image_base)?;
                             }
 
                             Ok(())
@@ -85,3 +427,232 @@ impl CodeWriter for OffsetMap {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::analysis::ModuleMetadata;
+
+    #[test]
+    fn build_number() -> Result<()> {
+        let offsets = OffsetMap::from([(
+            "engine2.dll".to_string(),
+            BTreeMap::from([("dwBuildNumber".to_string(), 0x100)]),
+        )]);
+
+        let modules = ModuleMap::from([(
+            "engine2.dll".to_string(),
+            ModuleMetadata {
+                base: Some(0x7FF800000000),
+                size: Some(0x600000),
+                image_base: 0x180000000,
+                text_hash: None,
+            },
+        )]);
+
+        let table = OffsetTable {
+            offsets: &offsets,
+            modules: &modules,
+            address_mode: AddressMode::Absolute,
+            build_number: Some(("engine2.dll", 5812)),
+            prologues: None,
+        };
+
+        let mut out = String::new();
+
+        table.write_json(&mut Formatter::new(&mut out, 4))?;
+
+        let value: serde_json::Value = serde_json::from_str(&out)?;
+
+        assert_eq!(value["engine2.dll"]["dwBuildNumber"], 0x180000100u64);
+        assert!(value["engine2.dll"].get("build_number").is_none());
+        assert_eq!(value["engine2.dll"][MODULE_KEY]["base"], 0x7FF800000000u64);
+        assert_eq!(value["engine2.dll"][MODULE_KEY]["size"], 0x600000);
+        assert_eq!(
+            value["engine2.dll"][MODULE_KEY]["image_base"],
+            0x180000000u64
+        );
+
+        let mut out = String::new();
+
+        table.write_cs(&mut Formatter::new(&mut out, 4))?;
+
+        assert!(out.contains("public const uint build_number = 5812;"));
+
+        let mut out = String::new();
+
+        table.write_rs(&mut Formatter::new(&mut out, 4))?;
+
+        assert!(out.starts_with(
+            "/// Build number: 5812\n#[allow(non_upper_case_globals, unused)]\npub mod deadlock_dumper"
+        ));
+        assert!(out.contains("pub const dwBuildNumber: u64 = 0x180000100;"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn image_base() -> Result<()> {
+        let offsets = OffsetMap::from([
+            (
+                "client.dll".to_string(),
+                BTreeMap::from([("dwEntityList".to_string(), 0x10)]),
+            ),
+            (
+                "engine2.dll".to_string(),
+                BTreeMap::from([("dwBuildNumber".to_string(), 0x100)]),
+            ),
+        ]);
+
+        let modules = ModuleMap::from([(
+            "client.dll".to_string(),
+            ModuleMetadata {
+                image_base: 0x180000000,
+                ..Default::default()
+            },
+        )]);
+
+        let table = OffsetTable {
+            offsets: &offsets,
+            modules: &modules,
+            address_mode: AddressMode::Rva,
+            build_number: None,
+            prologues: None,
+        };
+
+        let write = |write: fn(&OffsetTable<'_>, &mut Formatter<'_>) -> fmt::Result| {
+            let mut out = String::new();
+
+            write(&table, &mut Formatter::new(&mut out, 4)).map(|_| out)
+        };
+
+        let cs = write(OffsetTable::write_cs)?;
+        let hpp = write(OffsetTable::write_hpp)?;
+        let rs = write(OffsetTable::write_rs)?;
+
+        assert!(cs.contains("public const ulong image_base = 0x180000000;"));
+        assert!(hpp.contains("constexpr std::uintptr_t image_base = 0x180000000;"));
+        assert!(rs.contains("pub const image_base: usize = 0x180000000;"));
+        assert!(rs.contains("pub const dwEntityList: u32 = 0x10;"));
+        assert!(hpp.contains("namespace client {"));
+        assert!(rs.contains("pub mod engine2 {"));
+
+        // Modules without metadata don't get one.
+        assert_eq!(rs.matches("image_base").count(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn markdown() -> Result<()> {
+        let offsets = OffsetMap::from([
+            (
+                "client.dll".to_string(),
+                BTreeMap::from([("dwEntityList".to_string(), 0x10)]),
+            ),
+            (
+                "engine2.dll".to_string(),
+                BTreeMap::from([("dwBuildNumber".to_string(), 0x100)]),
+            ),
+        ]);
+
+        let modules = ModuleMap::from([(
+            "client.dll".to_string(),
+            ModuleMetadata {
+                image_base: 0x180000000,
+                ..Default::default()
+            },
+        )]);
+
+        let table = OffsetTable {
+            offsets: &offsets,
+            modules: &modules,
+            address_mode: AddressMode::Rva,
+            build_number: Some(("engine2.dll", 5812)),
+            prologues: None,
+        };
+
+        let mut out = String::new();
+
+        table.write_markdown(&mut Formatter::new(&mut out, 4))?;
+
+        assert_eq!(
+            out,
+            "# Offsets (build 5812)\n\n\
+             ## client.dll\n\n\
+             | Name | RVA | Address |\n\
+             | --- | --- | --- |\n\
+             | `dwEntityList` | `0x10` | `0x180000010` |\n\n\
+             ## engine2.dll\n\n\
+             | Name | RVA | Address |\n\
+             | --- | --- | --- |\n\
+             | `dwBuildNumber` | `0x100` | - |\n\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn id_table() -> Result<()> {
+        let offsets = OffsetMap::from([(
+            "client.dll".to_string(),
+            BTreeMap::from([
+                ("dwEntityList".to_string(), 0x10),
+                ("dwEntityList_site".to_string(), 0x20),
+            ]),
+        )]);
+
+        let modules = ModuleMap::new();
+
+        let table = OffsetTable {
+            offsets: &offsets,
+            modules: &modules,
+            address_mode: AddressMode::Rva,
+            build_number: None,
+            prologues: None,
+        };
+
+        let mut out = String::new();
+
+        table.write_id_table(&mut Formatter::new(&mut out, 4))?;
+
+        assert!(out.contains("    DEADLOCK_OFFSET_client_dll_dwEntityList = 0,\n"));
+        assert!(out.contains("    DEADLOCK_OFFSET_engine2_dll_dwBuildNumber = 19,\n"));
+        assert!(out.contains("    [DEADLOCK_OFFSET_client_dll_dwEntityList] = 0x10,\n"));
+
+        // Sites don't have an ID, and offsets that weren't found don't have a value.
+        assert!(!out.contains("dwEntityList_site"));
+        assert!(!out.contains("[DEADLOCK_OFFSET_engine2_dll_dwBuildNumber]"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn stripped_names() -> Result<()> {
+        let offsets = OffsetMap::from([(
+            "client.dll".to_string(),
+            BTreeMap::from([
+                ("dwEntityList".to_string(), 0x10),
+                ("fnGetCmd".to_string(), 0x20),
+            ]),
+        )]);
+
+        let stripped = strip_prefix(&offsets, "dw")?;
+
+        assert_eq!(stripped["client.dll"]["EntityList"], 0x10);
+        assert_eq!(stripped["client.dll"]["fnGetCmd"], 0x20);
+
+        let colliding = OffsetMap::from([(
+            "client.dll".to_string(),
+            BTreeMap::from([
+                ("dwEntityList".to_string(), 0x10),
+                ("EntityList".to_string(), 0x20),
+            ]),
+        )]);
+
+        assert!(strip_prefix(&colliding, "dw").is_err());
+
+        Ok(())
+    }
+}
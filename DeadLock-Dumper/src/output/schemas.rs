@@ -5,7 +5,7 @@ use heck::{AsPascalCase, AsSnakeCase};
 
 use serde_json::json;
 
-use super::{CodeWriter, Formatter, SchemaMap, slugify};
+use super::{CodeWriter, Formatter, SchemaMap, rs_ident, slugify};
 
 use crate::analysis::ClassMetadata;
 
@@ -207,10 +207,20 @@ impl CodeWriter for SchemaMap {
                 let classes: BTreeMap<_, _> = classes
                     .iter()
                     .map(|class| {
+                        // Fields without metadata still get an empty array, so every field has
+                        // the same shape.
                         let fields: BTreeMap<_, _> = class
                             .fields
                             .iter()
-                            .map(|field| (&field.name, field.offset))
+                            .map(|field| {
+                                (
+                                    &field.name,
+                                    json!({
+                                        "offset": field.offset,
+                                        "metadata": field.metadata,
+                                    }),
+                                )
+                            })
                             .collect();
 
                         let metadata: Vec<_> = class
@@ -238,6 +248,7 @@ impl CodeWriter for SchemaMap {
                             json!({
                                 "parent": class.parent_name,
                                 "fields": fields,
+                                "netvars": class.netvars,
                                 "metadata": metadata
                             }),
                         )
@@ -372,7 +383,9 @@ impl CodeWriter for SchemaMap {
                                             writeln!(
                                                 fmt,
                                                 "pub const {}: usize = {:#X}; // {}",
-                                                field.name, field.offset, field.type_name
+                                                rs_ident(&field.name),
+                                                field.offset,
+                                                field.type_name
                                             )?;
                                         }
 
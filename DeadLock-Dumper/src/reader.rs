@@ -0,0 +1,262 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use anyhow::{Result, anyhow};
+
+use memflow::prelude::v1::*;
+
+use serde_json::Value;
+
+/// The offsets and schema fields of `client.dll`, as read back from a previous dump.
+#[derive(Debug, Default)]
+pub struct Layout {
+    offsets: BTreeMap<String, u64>,
+    fields: BTreeMap<String, BTreeMap<String, u64>>,
+}
+
+impl Layout {
+    /// Loads `offsets.json` and `client_dll.json` from `dir`. The offsets have to be written as
+    /// RVAs, which is the default.
+    pub fn load(dir: &Path) -> Result<Self> {
+        let offsets: Value = serde_json::from_str(&fs::read_to_string(dir.join("offsets.json"))?)?;
+        let schemas: Value =
+            serde_json::from_str(&fs::read_to_string(dir.join("client_dll.json"))?)?;
+
+        let offsets = offsets
+            .get("client.dll")
+            .and_then(Value::as_object)
+            .ok_or_else(|| anyhow!("no client.dll offsets in offsets.json"))?
+            .iter()
+            .filter_map(|(name, value)| Some((name.clone(), value.as_u64()?)))
+            .collect();
+
+        let fields = schemas
+            .get("client.dll")
+            .and_then(|module| module.get("classes"))
+            .and_then(Value::as_object)
+            .ok_or_else(|| anyhow!("no client.dll classes in client_dll.json"))?
+            .iter()
+            .map(|(class_name, class)| {
+                let fields = class
+                    .get("fields")
+                    .and_then(Value::as_object)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|(name, field)| {
+                        Some((name.clone(), field.get("offset")?.as_u64()?))
+                    })
+                    .collect();
+
+                (class_name.clone(), fields)
+            })
+            .collect();
+
+        Ok(Self { offsets, fields })
+    }
+
+    pub fn offset(&self, name: &str) -> Result<u64> {
+        self.offsets
+            .get(name)
+            .copied()
+            .ok_or_else(|| anyhow!("offset not found: {}", name))
+    }
+
+    /// The offset of a field declared by `class_name` (not one of its base classes).
+    pub fn field(&self, class_name: &str, field_name: &str) -> Result<u64> {
+        self.fields
+            .get(class_name)
+            .and_then(|fields| fields.get(field_name))
+            .copied()
+            .ok_or_else(|| anyhow!("field not found: {}::{}", class_name, field_name))
+    }
+}
+
+/// The entity indices reserved for player controllers, one for each player slot.
+const PLAYER_SLOTS: RangeInclusive<u64> = 1..=64;
+
+/// The chunk pointers of the entity list start right after its vtable and padding.
+const ENTITY_CHUNKS_OFFSET: u64 = 0x10;
+
+/// Reads the local player's entities through the pointer chains described by a [`Layout`].
+pub struct EntityReader<'a, P> {
+    process: &'a mut P,
+    client_base: Address,
+    layout: Layout,
+}
+
+impl<'a, P: Process + MemoryView> EntityReader<'a, P> {
+    pub fn new(process: &'a mut P, layout: Layout) -> Result<Self> {
+        let client_base = process.module_by_name("client.dll")?.base;
+
+        Ok(Self {
+            process,
+            client_base,
+            layout,
+        })
+    }
+
+    /// Like [`new`](Self::new), but loads the layout from the output directory of a previous dump.
+    pub fn load(process: &'a mut P, dir: &Path) -> Result<Self> {
+        Self::new(process, Layout::load(dir)?)
+    }
+
+    pub fn layout(&self) -> &Layout {
+        &self.layout
+    }
+
+    /// Reads the pointer stored in the `client.dll` global `name`.
+    pub fn global(&mut self, name: &str) -> Result<Address> {
+        let offset = self.layout.offset(name)?;
+
+        Ok(self
+            .process
+            .read_addr64(self.client_base + offset)
+            .data_part()?)
+    }
+
+    /// Reads a field of the entity at `entity`.
+    pub fn field<T: Pod>(
+        &mut self,
+        entity: Address,
+        class_name: &str,
+        field_name: &str,
+    ) -> Result<T> {
+        let offset = self.layout.field(class_name, field_name)?;
+
+        Ok(self.process.read(entity + offset).data_part()?)
+    }
+
+    /// Looks up the entity at `index` in `dwEntityList`, which is split into chunks of
+    /// `dwEntityList_chunkSize` entries of `dwEntityList_entrySize` bytes, each starting with a
+    /// pointer to its entity. Returns `None` if the index isn't in use.
+    pub fn entity(&mut self, index: u64) -> Result<Option<Address>> {
+        let chunk_size = self.layout.offset("dwEntityList_chunkSize")?;
+        let entry_size = self.layout.offset("dwEntityList_entrySize")?;
+
+        let chunk_index = index
+            .checked_div(chunk_size)
+            .ok_or_else(|| anyhow!("invalid entity list chunk size"))?;
+
+        let entity_list = self.global("dwEntityList")?;
+
+        let chunk = self
+            .process
+            .read_addr64(entity_list + ENTITY_CHUNKS_OFFSET + chunk_index * 8)
+            .data_part()?;
+
+        if chunk.is_null() {
+            return Ok(None);
+        }
+
+        let entity = self
+            .process
+            .read_addr64(chunk + (index % chunk_size) * entry_size)
+            .data_part()?;
+
+        Ok((!entity.is_null()).then_some(entity))
+    }
+
+    /// The controllers of every connected player, which are the entities in the player slots.
+    pub fn player_controllers(&mut self) -> Result<Vec<Address>> {
+        let mut controllers = Vec::new();
+
+        for index in PLAYER_SLOTS {
+            if let Some(controller) = self.entity(index)? {
+                controllers.push(controller);
+            }
+        }
+
+        Ok(controllers)
+    }
+
+    pub fn local_controller(&mut self) -> Result<Address> {
+        self.global("dwLocalPlayerController")
+    }
+
+    pub fn local_pawn(&mut self) -> Result<Address> {
+        self.global("dwLocalPlayerPawn")
+    }
+
+    pub fn local_player_name(&mut self) -> Result<String> {
+        let controller = self.local_controller()?;
+        let offset = self
+            .layout
+            .field("CBasePlayerController", "m_iszPlayerName")?;
+
+        Ok(self
+            .process
+            .read_utf8(controller + offset, 128)
+            .data_part()?)
+    }
+
+    /// The `C_CitadelGameRules` of the current match, which is null outside of one.
+    pub fn game_rules(&mut self) -> Result<Address> {
+        self.global("dwGameRules")
+    }
+
+    /// The local player's view angles (pitch, yaw and roll) in degrees, which are held by the camera
+    /// manager rather than behind a pointer.
+    pub fn view_angles(&mut self) -> Result<[f32; 3]> {
+        let offset = self.layout.offset("dwViewAngles")?;
+
+        Ok(self.process.read(self.client_base + offset).data_part()?)
+    }
+
+    /// The absolute origin of the local pawn's scene node.
+    pub fn local_origin(&mut self) -> Result<[f32; 3]> {
+        let pawn = self.local_pawn()?;
+        let scene_node: u64 = self.field(pawn, "C_BaseEntity", "m_pGameSceneNode")?;
+
+        self.field(scene_node.into(), "CGameSceneNode", "m_vecAbsOrigin")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    fn layout() -> Result<()> {
+        let temp = tempfile::tempdir()?;
+        let dir = temp.path();
+
+        fs::write(
+            dir.join("offsets.json"),
+            json!({
+                "client.dll": { "dwLocalPlayerPawn": 0x1000 },
+                "version": 1,
+                "build_number": null,
+            })
+            .to_string(),
+        )?;
+
+        fs::write(
+            dir.join("client_dll.json"),
+            json!({
+                "client.dll": {
+                    "classes": {
+                        "C_BaseEntity": {
+                            "parent": null,
+                            "fields": { "m_pGameSceneNode": { "offset": 0x330, "metadata": [] } },
+                        },
+                    },
+                    "enums": {},
+                },
+            })
+            .to_string(),
+        )?;
+
+        let layout = Layout::load(dir)?;
+
+        assert_eq!(layout.offset("dwLocalPlayerPawn")?, 0x1000);
+        assert_eq!(layout.field("C_BaseEntity", "m_pGameSceneNode")?, 0x330);
+        assert!(layout.field("C_BaseEntity", "m_iHealth").is_err());
+        assert!(layout.offset("dwEntityList").is_err());
+
+        Ok(())
+    }
+}
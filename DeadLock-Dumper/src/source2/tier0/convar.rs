@@ -0,0 +1,23 @@
+use memflow::prelude::v1::*;
+
+use crate::source2::UtlLinkedList;
+
+#[derive(Pod)]
+#[repr(C)]
+pub struct ConVarData {
+    pub name: Pointer64<ReprCString>,         // 0x0000
+    pub default_value: Pointer64<[u8; 0x10]>, // 0x0008
+    pad_0: [u8; 0x18],                        // 0x0010
+    pub r#type: i16,                          // 0x0028
+    pad_1: [u8; 0x6],                         // 0x002A
+    pub flags: u64,                           // 0x0030
+}
+
+/// The `VEngineCvar007` interface exposed by `tier0.dll`.
+#[repr(C)]
+pub struct Cvar {
+    pad_0: [u8; 0x40],                                 // 0x0000
+    pub convars: UtlLinkedList<Pointer64<ConVarData>>, // 0x0040
+}
+
+unsafe impl Pod for Cvar {}
@@ -1,3 +1,5 @@
+pub use convar::*;
 pub use ts_list::*;
 
+pub mod convar;
 pub mod ts_list;
@@ -1,10 +1,12 @@
 pub use interface::*;
+pub use utl_linked_list::*;
 pub use utl_memory::*;
 pub use utl_memory_pool::*;
 pub use utl_ts_hash::*;
 pub use utl_vector::*;
 
 pub mod interface;
+pub mod utl_linked_list;
 pub mod utl_memory;
 pub mod utl_memory_pool;
 pub mod utl_ts_hash;
@@ -0,0 +1,49 @@
+use memflow::prelude::v1::*;
+
+use super::UtlMemory;
+
+/// The index that marks the end of the list.
+pub const INVALID_INDEX: u16 = u16::MAX;
+
+#[repr(C)]
+pub struct UtlLinkedListElem<T> {
+    pub element: T,    // 0x0000
+    pub previous: u16, // 0x0008
+    pub next: u16,     // 0x000A
+    pad_0: [u8; 0x4],  // 0x000C
+}
+
+unsafe impl<T: 'static> Pod for UtlLinkedListElem<T> {}
+
+#[repr(C)]
+pub struct UtlLinkedList<T> {
+    pub memory: UtlMemory<UtlLinkedListElem<T>>, // 0x0000
+    pub head: u16,                               // 0x0010
+    pub tail: u16,                               // 0x0012
+    pub first_free: u16,                         // 0x0014
+    pub count: u16,                              // 0x0016
+    pub allocated: u16,                          // 0x0018
+    pad_0: [u8; 0x6],                            // 0x001A
+}
+
+unsafe impl<T: 'static> Pod for UtlLinkedList<T> {}
+
+impl<T: Pod> UtlLinkedList<T> {
+    /// Reads the elements in list order, stopping after `count` of them in case the links are
+    /// broken.
+    pub fn elements(&self, mem: &mut impl MemoryView) -> Result<Vec<T>> {
+        let mut elements = Vec::with_capacity(self.count as usize);
+
+        let mut index = self.head;
+
+        while index != INVALID_INDEX && elements.len() < self.count as usize {
+            let elem = self.memory.element(mem, index as usize)?;
+
+            elements.push(elem.element);
+
+            index = elem.next;
+        }
+
+        Ok(elements)
+    }
+}